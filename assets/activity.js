@@ -0,0 +1,55 @@
+// Draws a sparkline of recent changes in every `td.activity` of the mare table,
+// from /api/v1/activity.
+(function () {
+    const DAYS = 14;
+    const BAR_WIDTH = 4;
+    const HEIGHT = 20;
+    const SVG = "http://www.w3.org/2000/svg";
+
+    function sparkline(counts, max) {
+        const svg = document.createElementNS(SVG, "svg");
+        svg.setAttribute("width", counts.length * BAR_WIDTH);
+        svg.setAttribute("height", HEIGHT);
+
+        counts.forEach((count, day) => {
+            const height = count === 0 ? 1 : Math.max(2, Math.round((count / max) * HEIGHT));
+            const bar = document.createElementNS(SVG, "rect");
+            bar.setAttribute("x", day * BAR_WIDTH);
+            bar.setAttribute("y", HEIGHT - height);
+            bar.setAttribute("width", BAR_WIDTH - 1);
+            bar.setAttribute("height", height);
+            bar.setAttribute("fill", count === 0 ? "#dee2e6" : "var(--site-accent)");
+            svg.appendChild(bar);
+        });
+
+        return svg;
+    }
+
+    async function draw() {
+        const cells = document.querySelectorAll("td.activity[data-mare-id]");
+        if (cells.length === 0) {
+            return;
+        }
+
+        const response = await fetch(`/api/v1/activity?days=${DAYS}`);
+        if (!response.ok) {
+            return;
+        }
+
+        const activity = await response.json();
+        const max = Math.max(1, ...Object.values(activity.mares).flat().map(([, count]) => count));
+
+        for (const cell of cells) {
+            const counts = new Array(activity.days).fill(0);
+            for (const [day, count] of activity.mares[cell.dataset.mareId] || []) {
+                counts[day] = count;
+            }
+
+            const total = counts.reduce((sum, count) => sum + count, 0);
+            cell.title = `${total} changes in the last ${activity.days} days`;
+            cell.replaceChildren(sparkline(counts, max));
+        }
+    }
+
+    document.addEventListener("DOMContentLoaded", draw);
+})();
@@ -0,0 +1,11 @@
+// Fills the nonce of the edit form in the browser, as the page itself may be
+// cached by the CDN and shared between visitors.
+(function () {
+    const nonce = document.querySelector("#edit input[name=nonce]");
+    if (!nonce) {
+        return;
+    }
+
+    const bytes = crypto.getRandomValues(new Uint8Array(16));
+    nonce.value = Array.from(bytes, (byte) => byte.toString(16).padStart(2, "0")).join("");
+})();
@@ -0,0 +1,32 @@
+// Uploads the chosen backup to /admin/restore as the body of the request,
+// as the server doesn't parse multipart forms.
+(function () {
+    const form = document.getElementById("restore");
+    if (!form) {
+        return;
+    }
+
+    form.addEventListener("submit", async (event) => {
+        event.preventDefault();
+
+        const file = form.querySelector("input[type=file]").files[0];
+        if (!file) {
+            return;
+        }
+
+        const response = await fetch(form.action, {
+            method: "POST",
+            headers: { "Content-Type": "application/json" },
+            body: file,
+        });
+
+        if (response.ok) {
+            window.location.reload();
+            return;
+        }
+
+        document.open();
+        document.write(await response.text());
+        document.close();
+    });
+})();
@@ -0,0 +1,32 @@
+-- every write to `mares` is recorded here in the same transaction
+create table if not exists mare_events (
+             id bigserial    primary key,
+        mare_id varchar(26)  not null,
+           kind varchar(16)  not null,
+        payload jsonb        not null,
+     request_id text,
+     created_at timestamptz  not null     default now()
+);
+
+create table if not exists webhooks (
+             id varchar(26)  primary key,
+            url text         not null,
+         events text[]       not null,
+         secret text         not null,
+     created_at timestamptz  not null     default now()
+);
+
+-- outbox: one row per event and webhook subscribed to it
+create table if not exists webhook_deliveries (
+       event_id bigint       not null     references mare_events (id) on delete cascade,
+     webhook_id varchar(26)  not null     references webhooks (id) on delete cascade,
+       attempts integer      not null     default 0,
+next_attempt_at timestamptz  not null     default now(),
+   delivered_at timestamptz,
+     last_error text,
+    primary key (event_id, webhook_id)
+);
+
+create index if not exists webhook_deliveries_pending
+    on webhook_deliveries (next_attempt_at)
+    where delivered_at is null;
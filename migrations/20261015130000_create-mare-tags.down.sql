@@ -0,0 +1 @@
+drop table mare_tags;
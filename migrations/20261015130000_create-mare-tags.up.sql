@@ -0,0 +1,7 @@
+create table if not exists mare_tags (
+        mare_id varchar(26)  not null     references mares (id) on delete cascade,
+            tag varchar(50)  not null,
+    primary key (mare_id, tag)
+);
+
+create index if not exists mare_tags_tag on mare_tags (tag);
@@ -0,0 +1,9 @@
+-- images shown in the personal galleries of mares
+create table if not exists mare_images (
+        mare_id varchar(26)  not null,
+       image_id bigint       not null,
+            url text         not null,
+     source_url text         not null,
+     fetched_at timestamptz  not null     default now(),
+    primary key (mare_id, image_id)
+);
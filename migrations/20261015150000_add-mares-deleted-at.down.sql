@@ -0,0 +1,3 @@
+drop index mares_deleted_at;
+
+alter table mares drop column deleted_at;
@@ -0,0 +1,4 @@
+-- deleted records are kept with the time of deletion
+alter table mares add column if not exists deleted_at timestamptz;
+
+create index if not exists mares_deleted_at on mares (deleted_at) where deleted_at is not null;
@@ -0,0 +1,6 @@
+alter table webhook_deliveries alter column webhook_id type varchar(26);
+alter table webhooks alter column id type varchar(26);
+alter table mare_events alter column mare_id type varchar(26);
+alter table mare_images alter column mare_id type varchar(26);
+alter table mare_tags alter column mare_id type varchar(26);
+alter table mares alter column id type varchar(26);
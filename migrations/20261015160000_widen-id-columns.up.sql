@@ -0,0 +1,7 @@
+-- fits UUIDs, the longest of the supported id formats
+alter table mares alter column id type varchar(36);
+alter table mare_tags alter column mare_id type varchar(36);
+alter table mare_images alter column mare_id type varchar(36);
+alter table mare_events alter column mare_id type varchar(36);
+alter table webhooks alter column id type varchar(36);
+alter table webhook_deliveries alter column webhook_id type varchar(36);
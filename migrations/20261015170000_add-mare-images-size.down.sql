@@ -0,0 +1 @@
+alter table mare_images drop column size_bytes;
@@ -0,0 +1,2 @@
+-- size of the original image, unknown for images fetched before it was recorded
+alter table mare_images add column if not exists size_bytes bigint;
@@ -0,0 +1,3 @@
+alter table mares drop column edit_nonce;
+
+alter table mares drop column edited_by;
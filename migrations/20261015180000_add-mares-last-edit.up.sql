@@ -0,0 +1,3 @@
+-- who saved the record last and from which form, to explain edit conflicts
+alter table mares add column if not exists edited_by text;
+alter table mares add column if not exists edit_nonce text;
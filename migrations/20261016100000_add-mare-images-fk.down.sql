@@ -0,0 +1 @@
+alter table mare_images drop constraint if exists mare_images_mare_id_fkey;
@@ -0,0 +1,7 @@
+-- images of records that were removed for good can't be shown anyway
+delete from mare_images
+where not exists (select from mares where mares.id = mare_images.mare_id);
+
+alter table mare_images drop constraint if exists mare_images_mare_id_fkey;
+alter table mare_images add constraint mare_images_mare_id_fkey
+    foreign key (mare_id) references mares (id) on delete cascade on update cascade;
@@ -0,0 +1,3 @@
+alter table mare_tags drop constraint if exists mare_tags_mare_id_fkey;
+alter table mare_tags add constraint mare_tags_mare_id_fkey
+    foreign key (mare_id) references mares (id) on delete cascade;
@@ -0,0 +1 @@
+alter table mare_images add column if not exists size_bytes bigint;
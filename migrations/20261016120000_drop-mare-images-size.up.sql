@@ -0,0 +1,2 @@
+-- the size of a kept image is the size of its stored file
+alter table mare_images drop column if exists size_bytes;
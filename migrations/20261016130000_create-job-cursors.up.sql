@@ -0,0 +1,5 @@
+-- last event handled by jobs that follow `mare_events`, kept across restarts
+create table if not exists job_cursors (
+            job varchar(32)  primary key,
+       event_id bigint       not null
+);
@@ -0,0 +1 @@
+drop index if exists mare_events_created_at;
@@ -0,0 +1,2 @@
+-- daily activity reads the recent part of the history
+create index if not exists mare_events_created_at on mare_events (created_at);
@@ -0,0 +1,3 @@
+drop index mares_created_at;
+
+alter table mares drop column created_at;
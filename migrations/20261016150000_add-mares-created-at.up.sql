@@ -0,0 +1,15 @@
+-- records are listed and paged by creation time, as ids of some formats don't follow it
+alter table mares add column if not exists created_at timestamptz;
+
+-- the first recorded change is the creation, if the history goes back that far
+update mares
+set created_at = coalesce(
+    (select min(created_at) from mare_events where mare_events.mare_id = mares.id),
+    modified_at
+)
+where created_at is null;
+
+alter table mares alter column created_at set default now();
+alter table mares alter column created_at set not null;
+
+create index if not exists mares_created_at on mares (created_at, id);
@@ -0,0 +1,328 @@
+use anyhow::anyhow;
+use askama_axum::Template;
+use axum::{
+    body::Bytes,
+    extract::{Path, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_extra::extract::Form;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use serde::Deserialize;
+use url::Url;
+
+use super::{app_error::AppError, layout::Layout, AppState};
+use crate::archive::Archive;
+use crate::database::doctor::Anomaly;
+use crate::database::events::MareEventKind;
+use crate::database::images::StoredImage;
+use crate::database::webhooks::{NewWebhook, Webhook};
+use crate::database::Database;
+use crate::jobs::{self, JobStatus, JobStatuses};
+use crate::storage::{ImageStorage, StoredFile};
+use crate::utils::id::DbId;
+use crate::utils::token;
+
+const ADMIN_USER: &str = "admin";
+
+/// Protects admin pages with HTTP basic auth.
+pub(crate) async fn require_admin(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(password) = &state.config.admin.password else {
+        return AppError::with_status_404(anyhow!("Admin pages are disabled.")).into_response();
+    };
+
+    let credentials = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| STANDARD.decode(encoded).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+
+    let authorized = credentials.is_some_and(|credentials| {
+        token::constant_time_eq(
+            credentials.as_bytes(),
+            format!("{ADMIN_USER}:{password}").as_bytes(),
+        )
+    });
+
+    if !authorized {
+        let mut response = AppError::new(
+            StatusCode::UNAUTHORIZED,
+            anyhow!("Only admins are allowed here."),
+        )
+        .into_response();
+
+        response.headers_mut().insert(
+            header::WWW_AUTHENTICATE,
+            HeaderValue::from_static(r#"Basic realm="admin""#),
+        );
+
+        return response;
+    }
+
+    next.run(request).await
+}
+
+#[derive(Debug, Template)]
+#[template(path = "admin_data.askama.html")]
+struct AdminDataTemplate {
+    layout: Layout,
+    summary: String,
+    anomalies: Vec<Anomaly>,
+    fixable: bool,
+}
+
+pub(crate) async fn get_data(State(pool): State<Database>) -> Result<impl IntoResponse, AppError> {
+    let anomalies = pool.check_data().await?;
+
+    Ok(AdminDataTemplate {
+        layout: Layout::current(),
+        summary: jobs::doctor::summary(&anomalies),
+        fixable: anomalies.iter().any(|anomaly| anomaly.kind.is_fixable()),
+        anomalies,
+    })
+}
+
+pub(crate) async fn repair_data(
+    State(pool): State<Database>,
+) -> Result<impl IntoResponse, AppError> {
+    pool.repair_data().await?;
+
+    Ok(Redirect::to("/admin/data"))
+}
+
+/// Downloads a backup that can be restored with the `restore` command.
+pub(crate) async fn get_backup(State(pool): State<Database>) -> Result<Response, AppError> {
+    let archive = pool.snapshot(true).await?;
+
+    let body = serde_json::to_vec_pretty(&archive)
+        .map_err(|err| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, err.into()))?;
+
+    let disposition = format!(
+        "attachment; filename=\"mare-backup-{}.json\"",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_owned()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Restores a backup from `/admin/backup` into an empty database. The backup is
+/// the body of the request, e.g. `curl --data-binary @backup.json`.
+pub(crate) async fn post_restore(
+    State(pool): State<Database>,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let archive =
+        Archive::read(body.as_ref()).map_err(|err| AppError::new(StatusCode::BAD_REQUEST, err))?;
+
+    let summary = pool.restore(&archive).await?;
+
+    Ok(format!(
+        "Restored {} records, {} tags, {} images and {} events",
+        summary.records, summary.tags, summary.images, summary.events,
+    ))
+}
+
+/// Number of images per page of `/admin/storage`.
+const STORAGE_PAGE_SIZE: i64 = 50;
+
+/// Kept image with the metadata of its file, `None` if the file is missing.
+#[derive(Debug)]
+struct StorageEntry {
+    image: StoredImage,
+    file: Option<StoredFile>,
+}
+
+#[derive(Debug, Template)]
+#[template(path = "admin_storage.askama.html")]
+struct AdminStorageTemplate {
+    layout: Layout,
+    entries: Vec<StorageEntry>,
+    total: i64,
+    page: i64,
+    pages: i64,
+}
+
+pub(crate) async fn get_storage(
+    State(pool): State<Database>,
+    State(storage): State<ImageStorage>,
+) -> Result<Response, AppError> {
+    storage_page(&pool, &storage, 1).await
+}
+
+pub(crate) async fn get_storage_page(
+    State(pool): State<Database>,
+    State(storage): State<ImageStorage>,
+    Path(page): Path<i64>,
+) -> Result<Response, AppError> {
+    storage_page(&pool, &storage, page).await
+}
+
+async fn storage_page(
+    pool: &Database,
+    storage: &ImageStorage,
+    page: i64,
+) -> Result<Response, AppError> {
+    let total = pool.count_images().await?;
+    let pages = ((total + STORAGE_PAGE_SIZE - 1) / STORAGE_PAGE_SIZE).max(1);
+
+    if page < 1 || page > pages {
+        return Err(AppError::with_status_404(anyhow!(
+            "Storage page {page} doesn't exist."
+        )));
+    }
+
+    // Files of deleted records stay stored until the records are purged
+    let images = pool
+        .records()
+        .include_deleted()
+        .list_images((page - 1) * STORAGE_PAGE_SIZE, STORAGE_PAGE_SIZE)
+        .await?;
+
+    let mut entries = Vec::with_capacity(images.len());
+    for image in images {
+        let file = storage
+            .metadata(image.image_id)
+            .await
+            .map_err(|err| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, err.into()))?;
+        entries.push(StorageEntry { image, file });
+    }
+
+    Ok(AdminStorageTemplate {
+        layout: Layout::current(),
+        entries,
+        total,
+        page,
+        pages,
+    }
+    .into_response())
+}
+
+/// Removes the image from the gallery, and its file once no gallery keeps it.
+pub(crate) async fn delete_image(
+    State(pool): State<Database>,
+    State(storage): State<ImageStorage>,
+    Path((mare_id, image_id)): Path<(DbId, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    pool.remove_image(mare_id, image_id).await?;
+
+    if !pool.is_image_kept(image_id).await? {
+        storage
+            .remove(image_id)
+            .await
+            .map_err(|err| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, err.into()))?;
+    }
+
+    Ok(Redirect::to("/admin/storage"))
+}
+
+#[derive(Debug, Template)]
+#[template(path = "admin_jobs.askama.html")]
+struct AdminJobsTemplate {
+    layout: Layout,
+    jobs: Vec<JobStatus>,
+}
+
+pub(crate) async fn get_jobs(State(statuses): State<JobStatuses>) -> impl IntoResponse {
+    AdminJobsTemplate {
+        layout: Layout::current(),
+        jobs: statuses.list(),
+    }
+}
+
+#[derive(Debug, Template)]
+#[template(path = "admin_webhooks.askama.html")]
+struct AdminWebhooksTemplate {
+    layout: Layout,
+    webhooks: Vec<Webhook>,
+    event_kinds: [MareEventKind; 3],
+}
+
+pub(crate) async fn get_webhooks(
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, AppError> {
+    let webhooks = state
+        .database
+        .list_webhooks(state.config.webhooks.max_attempts)
+        .await?;
+
+    Ok(AdminWebhooksTemplate {
+        layout: Layout::current(),
+        webhooks,
+        event_kinds: MareEventKind::ALL,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct AddWebhookForm {
+    url: String,
+    #[serde(default)]
+    events: Vec<MareEventKind>,
+    #[serde(default)]
+    secret: String,
+}
+
+pub(crate) async fn post_webhooks(
+    State(state): State<AppState>,
+    Form(form): Form<AddWebhookForm>,
+) -> Result<impl IntoResponse, AppError> {
+    let url = Url::parse(&form.url)
+        .map_err(|err| AppError::new(StatusCode::BAD_REQUEST, anyhow!("Invalid URL: {err}")))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Webhook URL must use http or https."),
+        ));
+    }
+
+    if form.events.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Select at least one event."),
+        ));
+    }
+
+    let secret = match form.secret.trim() {
+        "" => generate_secret(),
+        secret => secret.to_owned(),
+    };
+
+    let webhook = NewWebhook {
+        url: url.to_string(),
+        events: form.events,
+        secret,
+    };
+
+    state.database.add_webhook(&webhook).await?;
+
+    Ok(Redirect::to("/admin/webhooks"))
+}
+
+pub(crate) async fn delete_webhook(
+    State(state): State<AppState>,
+    Path(id): Path<DbId>,
+) -> Result<impl IntoResponse, AppError> {
+    state.database.remove_webhook(id).await?;
+
+    Ok(Redirect::to("/admin/webhooks"))
+}
+
+fn generate_secret() -> String {
+    token::random_hex(32)
+}
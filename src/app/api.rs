@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::app_error::AppError;
+use super::cache_tags::CacheTags;
+use crate::database::breed::Breed;
+use crate::database::Database;
+
+#[derive(Debug, Serialize)]
+pub(crate) struct BreedInfo {
+    code: &'static str,
+    name: String,
+    icon: &'static str,
+    color: &'static str,
+    count: i64,
+}
+
+/// All breeds with the number of records of each of them.
+pub(crate) async fn get_breeds(
+    State(pool): State<Database>,
+) -> Result<(CacheTags, Json<Vec<BreedInfo>>), AppError> {
+    let counts = pool.records().count_by_breed().await?;
+
+    let breeds = Breed::ALL
+        .into_iter()
+        .map(|breed| BreedInfo {
+            code: breed.code(),
+            name: breed.to_string(),
+            icon: breed.icon(),
+            color: breed.color(),
+            count: counts
+                .iter()
+                .find(|(counted, _)| *counted == breed)
+                .map_or(0, |(_, count)| *count),
+        })
+        .collect();
+
+    Ok((CacheTags::collection(), Json(breeds)))
+}
+
+/// Days of activity returned when the client doesn't ask for a number.
+const DEFAULT_ACTIVITY_DAYS: u32 = 14;
+const MAX_ACTIVITY_DAYS: u32 = 90;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct ActivityQuery {
+    days: Option<u32>,
+}
+
+/// Changes per mare per day as a sparse matrix: only mares and days with
+/// changes are listed.
+#[derive(Debug, Serialize)]
+pub(crate) struct Activity {
+    /// First day of the period, in UTC.
+    start: NaiveDate,
+    days: u32,
+    /// Pairs of a day offset from `start` and the number of changes, by mare id.
+    mares: BTreeMap<String, Vec<(u32, i64)>>,
+}
+
+/// Computed activity by the number of days. An entry is valid until the next
+/// change or until the period moves to the next day.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ActivityCache(Arc<Mutex<HashMap<u32, CachedActivity>>>);
+
+#[derive(Debug)]
+struct CachedActivity {
+    event_id: i64,
+    activity: Arc<Activity>,
+}
+
+pub(crate) async fn get_activity(
+    State(pool): State<Database>,
+    State(cache): State<ActivityCache>,
+    Query(query): Query<ActivityQuery>,
+) -> Result<(CacheTags, Json<Arc<Activity>>), AppError> {
+    let days = query.days.unwrap_or(DEFAULT_ACTIVITY_DAYS);
+    if !(1..=MAX_ACTIVITY_DAYS).contains(&days) {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Number of days must be from 1 to {MAX_ACTIVITY_DAYS}."),
+        ));
+    }
+
+    let start = Utc::now().date_naive() - Days::new(u64::from(days - 1));
+    let event_id = pool.events().latest();
+
+    if let Some(cached) = cache.0.lock().unwrap().get(&days) {
+        if cached.event_id == event_id && cached.activity.start == start {
+            return Ok((CacheTags::collection(), Json(cached.activity.clone())));
+        }
+    }
+
+    let mut mares: BTreeMap<String, Vec<(u32, i64)>> = BTreeMap::new();
+    for row in pool.daily_activity(start).await? {
+        let offset = (row.day - start).num_days();
+
+        mares
+            .entry(row.mare_id.to_string())
+            .or_default()
+            .push((u32::try_from(offset).unwrap_or_default(), row.changes));
+    }
+
+    let activity = Arc::new(Activity { start, days, mares });
+
+    cache.0.lock().unwrap().insert(
+        days,
+        CachedActivity {
+            event_id,
+            activity: activity.clone(),
+        },
+    );
+
+    Ok((CacheTags::collection(), Json(activity)))
+}
@@ -1,41 +1,83 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
 use askama::Template;
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
 
+use super::layout::Layout;
+use crate::database::DbError;
+
 #[derive(Debug, Template)]
 #[template(path = "error.askama.html")]
 struct ErrorTemplate {
+    layout: Layout,
     code: StatusCode,
     source: anyhow::Error,
+    retry_after: Option<u64>,
 }
 
 pub(crate) struct AppError {
     pub(crate) code: StatusCode,
     pub(crate) source: anyhow::Error,
+    pub(crate) retry_after: Option<Duration>,
 }
 
 impl AppError {
     pub(crate) fn new(code: StatusCode, source: anyhow::Error) -> Self {
-        Self { code, source }
+        Self {
+            code,
+            source,
+            retry_after: None,
+        }
     }
 
     pub(crate) fn with_status_404(source: anyhow::Error) -> Self {
+        Self::new(StatusCode::NOT_FOUND, source)
+    }
+
+    pub(crate) fn too_many_requests(retry_after: Duration) -> Self {
         Self {
-            code: StatusCode::NOT_FOUND,
-            source,
+            code: StatusCode::TOO_MANY_REQUESTS,
+            source: anyhow!("Too many requests. Please slow down, the mares need some rest."),
+            retry_after: Some(retry_after),
+        }
+    }
+
+    pub(crate) fn service_unavailable(retry_after: Duration) -> Self {
+        Self {
+            code: StatusCode::SERVICE_UNAVAILABLE,
+            source: anyhow!("The website is under maintenance. We will be back soon!"),
+            retry_after: Some(retry_after),
         }
     }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        ErrorTemplate {
+        // round up, so the client never retries too early
+        let retry_after = self
+            .retry_after
+            .map(|duration| duration.as_secs() + u64::from(duration.subsec_nanos() > 0));
+
+        let html = ErrorTemplate {
+            layout: Layout::current(),
             code: self.code,
             source: self.source,
+            retry_after,
+        };
+
+        let mut response = (self.code, html).into_response();
+
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
         }
-        .into_response()
+
+        response
     }
 }
 
@@ -44,9 +86,17 @@ where
     E: Into<anyhow::Error>,
 {
     fn from(err: E) -> Self {
-        Self {
-            code: StatusCode::NOT_FOUND,
-            source: err.into(),
-        }
+        let err = err.into();
+
+        let code = match err.downcast_ref::<DbError>() {
+            Some(DbError::NotFound) => StatusCode::NOT_FOUND,
+            Some(DbError::Conflict(_)) => StatusCode::CONFLICT,
+            Some(DbError::Constraint(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            Some(DbError::Io(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            Some(DbError::Other(_)) => StatusCode::INTERNAL_SERVER_ERROR,
+            None => StatusCode::NOT_FOUND,
+        };
+
+        Self::new(code, err)
     }
 }
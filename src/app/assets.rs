@@ -0,0 +1,128 @@
+//! Stylesheets and scripts served from `/assets`. They are embedded into the
+//! binary and hashed once, so pages can reference them with `integrity` attributes.
+
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use anyhow::anyhow;
+use axum::{
+    extract::Path,
+    http::header,
+    response::{IntoResponse, Json},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+use sha2::{Digest, Sha384};
+
+use super::app_error::AppError;
+
+/// Name, content type and contents of every asset.
+const EMBEDDED: [(&str, &str, &[u8]); 5] = [
+    (
+        "activity.js",
+        "text/javascript; charset=utf-8",
+        include_bytes!("../../assets/activity.js"),
+    ),
+    (
+        "edit.js",
+        "text/javascript; charset=utf-8",
+        include_bytes!("../../assets/edit.js"),
+    ),
+    (
+        "index.css",
+        "text/css; charset=utf-8",
+        include_bytes!("../../assets/index.css"),
+    ),
+    (
+        "restore.js",
+        "text/javascript; charset=utf-8",
+        include_bytes!("../../assets/restore.js"),
+    ),
+    (
+        "site.css",
+        "text/css; charset=utf-8",
+        include_bytes!("../../assets/site.css"),
+    ),
+];
+
+static ASSETS: OnceLock<Assets> = OnceLock::new();
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Asset {
+    /// Changes with the contents, so the asset can be cached forever.
+    url: String,
+    /// Subresource Integrity hash, e.g. `sha384-...`.
+    integrity: String,
+    size: usize,
+    #[serde(skip)]
+    content_type: &'static str,
+    #[serde(skip)]
+    bytes: &'static [u8],
+}
+
+#[derive(Debug)]
+pub(crate) struct Assets(BTreeMap<&'static str, Asset>);
+
+impl Assets {
+    /// Hashes the assets on the first call.
+    pub(crate) fn get() -> &'static Self {
+        ASSETS.get_or_init(Self::hash)
+    }
+
+    fn hash() -> Self {
+        let assets = EMBEDDED
+            .iter()
+            .map(|&(name, content_type, bytes)| {
+                let digest = Sha384::digest(bytes);
+
+                let asset = Asset {
+                    url: format!("/assets/{name}?v={}", hex::encode(&digest[..8])),
+                    integrity: format!("sha384-{}", STANDARD.encode(digest)),
+                    size: bytes.len(),
+                    content_type,
+                    bytes,
+                };
+
+                (name, asset)
+            })
+            .collect();
+
+        Self(assets)
+    }
+
+    /// Panics on unknown names, which can only come from templates.
+    fn asset(&self, name: &str) -> &Asset {
+        self.0
+            .get(name)
+            .unwrap_or_else(|| panic!("Asset \"{name}\" is not embedded"))
+    }
+
+    pub(crate) fn url(&self, name: &str) -> &str {
+        &self.asset(name).url
+    }
+
+    pub(crate) fn integrity(&self, name: &str) -> &str {
+        &self.asset(name).integrity
+    }
+}
+
+pub(crate) async fn get_asset(Path(name): Path<String>) -> Result<impl IntoResponse, AppError> {
+    let Some(asset) = Assets::get().0.get(name.as_str()) else {
+        return Err(AppError::with_status_404(anyhow!(
+            "Asset \"{name}\" doesn't exist."
+        )));
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, asset.content_type),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        asset.bytes,
+    ))
+}
+
+/// URLs and integrity hashes of all assets, for external tooling.
+pub(crate) async fn get_manifest() -> impl IntoResponse {
+    Json(&Assets::get().0)
+}
@@ -0,0 +1,106 @@
+use anyhow::anyhow;
+use askama_axum::Template;
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::Form;
+use serde::Deserialize;
+
+use super::app_error::AppError;
+use super::layout::Layout;
+use crate::database::breed::Breed;
+use crate::database::tags::MAX_TAG_LENGTH;
+use crate::database::{BatchOperation, Database, DatabaseRecord};
+use crate::utils::id::DbId;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BatchAction {
+    Delete,
+    SetBreed,
+    AddTag,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct BatchForm {
+    #[serde(default)]
+    ids: Vec<DbId>,
+    action: BatchAction,
+    breed: Option<Breed>,
+    #[serde(default)]
+    tag: String,
+}
+
+impl BatchForm {
+    fn operation(&self) -> Result<BatchOperation, AppError> {
+        let operation = match self.action {
+            BatchAction::Delete => BatchOperation::Delete,
+            BatchAction::SetBreed => {
+                let breed = self.breed.ok_or_else(|| {
+                    AppError::new(StatusCode::BAD_REQUEST, anyhow!("Choose a breed to set."))
+                })?;
+
+                BatchOperation::SetBreed(breed)
+            }
+            BatchAction::AddTag => {
+                let tag = self.tag.trim().to_lowercase();
+
+                if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+                    return Err(AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        anyhow!("Tag must be from 1 to {MAX_TAG_LENGTH} characters long."),
+                    ));
+                }
+
+                BatchOperation::AddTag(tag)
+            }
+        };
+
+        Ok(operation)
+    }
+}
+
+#[derive(Debug, Template)]
+#[template(path = "batch_result.askama.html")]
+struct BatchResultTemplate {
+    layout: Layout,
+    description: String,
+    records: Vec<DatabaseRecord>,
+    missing: Vec<DbId>,
+}
+
+pub(crate) async fn post_batch(
+    State(pool): State<Database>,
+    Form(form): Form<BatchForm>,
+) -> Result<impl IntoResponse, AppError> {
+    if form.ids.is_empty() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!("Select at least one mare."),
+        ));
+    }
+
+    let operation = form.operation()?;
+
+    let records = pool.batch(&form.ids, &operation).await?;
+
+    let missing = form
+        .ids
+        .iter()
+        .filter(|id| !records.iter().any(|record| record.id == **id))
+        .cloned()
+        .collect();
+
+    let description = match &operation {
+        BatchOperation::Delete => "Deleted".to_owned(),
+        BatchOperation::SetBreed(breed) => format!("Changed breed to {breed} for"),
+        BatchOperation::AddTag(tag) => format!("Added tag \"{tag}\" to"),
+    };
+
+    let html = BatchResultTemplate {
+        layout: Layout::current(),
+        description,
+        records,
+        missing,
+    };
+
+    Ok(html)
+}
@@ -0,0 +1,58 @@
+use axum::{
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    response::{IntoResponseParts, ResponseParts},
+};
+
+use crate::jobs::cdn::{record_key, COLLECTION_KEY};
+use crate::utils::id::DbId;
+
+/// Marks a response with the keys it is purged by, see [`crate::jobs::cdn`].
+/// Fastly reads `Surrogate-Key`, Cloudflare reads `Cache-Tag`.
+#[derive(Debug, Clone)]
+pub(crate) struct CacheTags(Vec<String>);
+
+/// Tagged pages are purged when they change, so the CDN may keep them for
+/// a day, while browsers check for a newer version on every visit.
+const CACHE_CONTROL: &str = "public, max-age=0, s-maxage=86400";
+
+/// Name of the header with the keys of a tagged response.
+const SURROGATE_KEY: &str = "surrogate-key";
+
+impl CacheTags {
+    /// Pages that list records.
+    pub(crate) fn collection() -> Self {
+        Self(vec![COLLECTION_KEY.to_owned()])
+    }
+
+    /// Pages of a single record.
+    pub(crate) fn record(id: DbId) -> Self {
+        Self(vec![record_key(id)])
+    }
+
+    /// Whether the response is cached by the CDN and shared between visitors,
+    /// so it must not carry anything personal, e.g. cookies.
+    pub(crate) fn are_set(headers: &HeaderMap) -> bool {
+        headers.contains_key(SURROGATE_KEY)
+    }
+}
+
+impl IntoResponseParts for CacheTags {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        // Keys only contain ids, so they are always valid header values
+        for (name, separator) in [(SURROGATE_KEY, " "), ("cache-tag", ",")] {
+            if let Ok(value) = HeaderValue::from_str(&self.0.join(separator)) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(name), value);
+            }
+        }
+
+        res.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(CACHE_CONTROL),
+        );
+
+        Ok(res)
+    }
+}
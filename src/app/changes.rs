@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Json},
+};
+use serde::{Deserialize, Serialize};
+
+use super::app_error::AppError;
+use crate::config::Config;
+use crate::database::events::Change;
+use crate::database::Database;
+
+/// Number of changes returned by a single poll.
+const MAX_CHANGES: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PollQuery {
+    /// Id of the last event the client has seen.
+    since: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct PollResponse {
+    /// Value of `since` for the next poll.
+    cursor: i64,
+    changes: Vec<Change>,
+}
+
+/// Long polling for clients that can't use streaming responses. Answers as soon
+/// as there are changes after `since`, or with no changes after a bounded wait.
+/// Without `since`, answers right away with the current cursor.
+pub(crate) async fn poll_changes(
+    State(pool): State<Database>,
+    State(config): State<Arc<Config>>,
+    Query(query): Query<PollQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let Some(since) = query.since else {
+        return Ok(response(pool.latest_event_id().await?, Vec::new()));
+    };
+
+    // Subscribed before the first query, so changes made in between aren't missed
+    let mut events = pool.events().subscribe();
+
+    let mut changes = pool.changes_since(since, MAX_CHANGES).await?;
+
+    if changes.is_empty() {
+        let wait = tokio::time::timeout(config.http.long_poll_wait, async {
+            while *events.borrow_and_update() <= since {
+                if events.changed().await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        if wait.await.is_ok() {
+            changes = pool.changes_since(since, MAX_CHANGES).await?;
+        }
+    }
+
+    let cursor = changes.last().map_or(since, |change| change.event_id);
+
+    Ok(response(cursor, changes))
+}
+
+fn response(cursor: i64, changes: Vec<Change>) -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "no-store")],
+        Json(PollResponse { cursor, changes }),
+    )
+}
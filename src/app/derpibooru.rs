@@ -0,0 +1,113 @@
+//! Client of the derpibooru API, the source of the images in mare galleries.
+
+use serde::Deserialize;
+use tracing::{info, info_span, Instrument};
+use url::Url;
+
+use crate::correlation;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Derpibooru {
+    client: reqwest::Client,
+    base_url: Url,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    images: Vec<Image>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ImageResponse {
+    image: Image,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Image {
+    pub(crate) id: i64,
+    pub(crate) representations: Representations,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct Representations {
+    // large: String,
+    pub(crate) medium: String,
+    // small: String,
+}
+
+impl Derpibooru {
+    pub(crate) fn new(base_url: Url) -> reqwest::Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!(
+                "MareWebsite",
+                env!("CARGO_PKG_VERSION"),
+                "https://github.com/nitkach",
+            ))
+            .build()?;
+
+        Ok(Self { client, base_url })
+    }
+
+    /// Page of the image on derpibooru.
+    pub(crate) fn source_url(&self, image_id: i64) -> String {
+        self.url(&image_id.to_string()).to_string()
+    }
+
+    /// Random popular image of the mare called `name`, if there are any.
+    pub(crate) async fn random_image(&self, name: &str) -> reqwest::Result<Option<Image>> {
+        let url = self.url("api/v1/json/search/images");
+        let tags = format!("score.gte:100, {name}, pony, mare, !irl");
+        let query = [("per_page", "1"), ("sf", "random"), ("q", &tags)];
+        let request = correlation::outbound(self.client.get(url.clone()).query(&query));
+
+        info!(%url, query = ?query, "Request created, sending...");
+        let mut response = request
+            .send()
+            .instrument(info_span!("derpibooru"))
+            .await?
+            .error_for_status()?
+            .json::<SearchResponse>()
+            .await?;
+
+        Ok(response.images.pop())
+    }
+
+    /// Image with `image_id`, `None` if it doesn't exist.
+    pub(crate) async fn image(&self, image_id: i64) -> reqwest::Result<Option<Image>> {
+        let url = self.url(&format!("api/v1/json/images/{image_id}"));
+        let request = correlation::outbound(self.client.get(url));
+
+        let response = request.send().instrument(info_span!("derpibooru")).await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let response = response.error_for_status()?.json::<ImageResponse>().await?;
+
+        Ok(Some(response.image))
+    }
+
+    /// Contents of an image file, e.g. of a representation of an image.
+    pub(crate) async fn download(&self, url: &str) -> reqwest::Result<Vec<u8>> {
+        let request = correlation::outbound(self.client.get(url));
+
+        let bytes = request
+            .send()
+            .instrument(info_span!("derpibooru"))
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        Ok(bytes.to_vec())
+    }
+
+    fn url(&self, path: &str) -> Url {
+        let mut url = self.base_url.clone();
+        let base = url.path().trim_end_matches('/').to_owned();
+        url.set_path(&format!("{base}/{path}"));
+
+        url
+    }
+}
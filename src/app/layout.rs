@@ -0,0 +1,34 @@
+use std::sync::{Arc, OnceLock};
+
+use super::assets::Assets;
+use crate::config::BrandingConfig;
+
+static BRANDING: OnceLock<Arc<BrandingConfig>> = OnceLock::new();
+
+/// Sets the branding shown by all pages and hashes the assets they use.
+/// Only the first call has an effect.
+pub(crate) fn init(branding: BrandingConfig) {
+    let _ = BRANDING.set(Arc::new(branding));
+    Assets::get();
+}
+
+/// Context of `base.askama.html`, shared by all pages.
+///
+/// It is global rather than a part of [`AppState`](super::AppState),
+/// since error pages are rendered without access to the state.
+#[derive(Debug, Clone)]
+pub(crate) struct Layout {
+    pub(crate) branding: Arc<BrandingConfig>,
+    pub(crate) assets: &'static Assets,
+}
+
+impl Layout {
+    pub(crate) fn current() -> Self {
+        let branding = BRANDING.get_or_init(|| Arc::new(BrandingConfig::default()));
+
+        Self {
+            branding: branding.clone(),
+            assets: Assets::get(),
+        }
+    }
+}
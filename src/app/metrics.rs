@@ -0,0 +1,35 @@
+use std::fmt::Write;
+use std::sync::atomic::Ordering;
+
+use axum::{http::header, response::IntoResponse};
+
+use crate::utils::id::ULID_CONTENTION;
+
+/// Counters in the Prometheus text format.
+pub(crate) async fn get_metrics() -> impl IntoResponse {
+    let mut metrics = String::new();
+
+    counter(
+        &mut metrics,
+        "mare_ulid_overflows_total",
+        "Times the ULID generator ran out of ids within a millisecond.",
+        ULID_CONTENTION.overflows.load(Ordering::Relaxed),
+    );
+    counter(
+        &mut metrics,
+        "mare_ulid_lock_waits_total",
+        "Times a ULID was generated while the generator was busy.",
+        ULID_CONTENTION.lock_waits.load(Ordering::Relaxed),
+    );
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics,
+    )
+}
+
+fn counter(metrics: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(metrics, "# HELP {name} {help}");
+    let _ = writeln!(metrics, "# TYPE {name} counter");
+    let _ = writeln!(metrics, "{name} {value}");
+}
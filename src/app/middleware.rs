@@ -0,0 +1,397 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, Extensions, HeaderMap, HeaderValue, StatusCode, Version},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tower_http::{
+    compression::{
+        predicate::{DefaultPredicate, Predicate},
+        CompressionLayer,
+    },
+    request_id::RequestId,
+};
+use tracing::{info_span, warn, Span};
+
+use super::{app_error::AppError, cache_tags::CacheTags, AppState};
+use crate::utils::token;
+use crate::{budget, config::RateLimitConfig, correlation};
+
+const FORWARDED_FOR_HEADER: &str = "x-forwarded-for";
+
+/// Error bodies larger than this are not worth showing to the user.
+const MAX_ERROR_BODY_SIZE: usize = 4096;
+
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    config: RateLimitConfig,
+    clients: Arc<Mutex<Clients>>,
+}
+
+#[derive(Debug)]
+struct Clients {
+    windows: HashMap<IpAddr, Window>,
+    /// Clients whose windows are over are forgotten at most once per window,
+    /// so requests don't scan the whole map when there are many clients.
+    last_swept: Instant,
+}
+
+#[derive(Debug)]
+struct Window {
+    started_at: Instant,
+    requests: u32,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            clients: Arc::new(Mutex::new(Clients {
+                windows: HashMap::new(),
+                last_swept: Instant::now(),
+            })),
+        }
+    }
+
+    /// Address of the client that made the request. Behind trusted proxies,
+    /// it is the last address in `x-forwarded-for` that wasn't added by one of them.
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let is_trusted = |ip: &IpAddr| {
+            self.config
+                .trusted_proxies
+                .iter()
+                .any(|network| network.contains(ip))
+        };
+
+        if !is_trusted(&peer) {
+            return peer;
+        }
+
+        let forwarded = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|ip| ip.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+
+        let mut client = peer;
+
+        for ip in forwarded.into_iter().rev() {
+            // A malformed entry can't be attributed to anyone, so the proxy that added it takes the blame
+            let Some(ip) = ip else {
+                break;
+            };
+
+            client = ip;
+
+            if !is_trusted(&client) {
+                break;
+            }
+        }
+
+        client
+    }
+
+    /// Registers a request from `ip`. Returns the time left until the client
+    /// can make requests again, if the limit is exceeded.
+    fn check(&self, ip: IpAddr) -> Option<Duration> {
+        if self.config.requests == 0 {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap();
+
+        // forget clients whose windows are already over, so the map doesn't grow forever
+        if clients.windows.len() > 1024
+            && now.duration_since(clients.last_swept) >= self.config.window
+        {
+            clients.last_swept = now;
+            clients
+                .windows
+                .retain(|_, window| now.duration_since(window.started_at) < self.config.window);
+        }
+
+        let window = clients.windows.entry(ip).or_insert(Window {
+            started_at: now,
+            requests: 0,
+        });
+
+        let elapsed = now.duration_since(window.started_at);
+        if elapsed >= self.config.window {
+            window.started_at = now;
+            window.requests = 0;
+        }
+
+        if window.requests >= self.config.requests {
+            return Some(self.config.window - now.duration_since(window.started_at));
+        }
+
+        window.requests += 1;
+
+        None
+    }
+}
+
+pub(crate) async fn maintenance(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let maintenance = &state.config.maintenance;
+
+    if maintenance.enabled {
+        return AppError::service_unavailable(maintenance.retry_after).into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Answers with 504 when a handler takes longer than `timeout`. Unlike 408,
+/// which `tower_http::timeout` uses, it doesn't blame the client for the wait.
+pub(crate) async fn timeout(
+    State(timeout): State<Duration>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!(?timeout, "Request timed out");
+
+            AppError::new(
+                StatusCode::GATEWAY_TIMEOUT,
+                anyhow!("The mares took too long to answer. Please try again later."),
+            )
+            .into_response()
+        }
+    }
+}
+
+pub(crate) async fn rate_limit(
+    State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let ip = state
+        .rate_limiter
+        .client_ip(address.ip(), request.headers());
+
+    if let Some(retry_after) = state.rate_limiter.check(ip) {
+        warn!(?ip, "Rate limit exceeded");
+        return AppError::too_many_requests(retry_after).into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Header with the token of internal clients, see [`crate::config::InternalConfig`].
+const INTERNAL_TOKEN_HEADER: &str = "x-internal-token";
+
+/// Answers with 504 and the steps done so far when a request of an internal
+/// client takes longer than the budget it set. Budgets of others are ignored.
+pub(crate) async fn enforce_budget(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(value) = request.headers().get(budget::REQUEST_BUDGET_HEADER) else {
+        return next.run(request).await;
+    };
+
+    let is_internal = state.config.internal.token.as_ref().is_some_and(|token| {
+        request
+            .headers()
+            .get(INTERNAL_TOKEN_HEADER)
+            .is_some_and(|value| token::constant_time_eq(value.as_bytes(), token.as_bytes()))
+    });
+
+    if !is_internal {
+        return next.run(request).await;
+    }
+
+    let Some(millis) = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .filter(|&millis| millis > 0)
+    else {
+        return AppError::new(
+            StatusCode::BAD_REQUEST,
+            anyhow!(
+                "Invalid {} header: expected a positive number of milliseconds.",
+                budget::REQUEST_BUDGET_HEADER
+            ),
+        )
+        .into_response();
+    };
+
+    match budget::scope(Duration::from_millis(millis), next.run(request)).await {
+        Ok(response) => response,
+        Err(exhausted) => {
+            warn!(?exhausted, "Request budget exceeded");
+
+            (StatusCode::GATEWAY_TIMEOUT, Json(exhausted)).into_response()
+        }
+    }
+}
+
+/// Anonymous id of a browser, kept in a cookie. Tells apart a user's own
+/// edits in another tab from edits of someone else.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ClientId(pub(crate) String);
+
+const CLIENT_ID_COOKIE: &str = "client_id";
+
+/// Length of a client id in bytes, before hex encoding.
+const CLIENT_ID_BYTES: usize = 16;
+
+/// Gives every browser a [`ClientId`], available to handlers as an extension.
+/// The cookie is not set on pages shared through the CDN, or every visitor of
+/// a cached page would get the same id. Browsers get it on their first edit.
+pub(crate) async fn identify_client(mut request: Request, next: Next) -> Response {
+    let known = client_id_of(request.headers());
+    let client_id = known
+        .clone()
+        .unwrap_or_else(|| ClientId(token::random_hex(CLIENT_ID_BYTES)));
+
+    request.extensions_mut().insert(client_id.clone());
+
+    let mut response = next.run(request).await;
+
+    if known.is_none() && !CacheTags::are_set(response.headers()) {
+        let cookie = format!(
+            "{CLIENT_ID_COOKIE}={}; Path=/; Max-Age=31536000; HttpOnly; SameSite=Lax",
+            client_id.0
+        );
+
+        if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+        }
+    }
+
+    response
+}
+
+fn client_id_of(headers: &HeaderMap) -> Option<ClientId> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == CLIENT_ID_COOKIE)
+        .map(|(_, value)| value)
+        .filter(|value| {
+            value.len() == CLIENT_ID_BYTES * 2 && value.bytes().all(|byte| byte.is_ascii_hexdigit())
+        })
+        .map(|value| ClientId(value.to_owned()))
+}
+
+/// Makes the request id available to everything the request triggers.
+pub(crate) async fn correlate(request: Request, next: Next) -> Response {
+    let request_id = request_id_of(&request).unwrap_or_default();
+
+    correlation::scope(request_id, next.run(request)).await
+}
+
+pub(crate) fn make_request_span(request: &Request) -> Span {
+    info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = request_id_of(request).unwrap_or_default(),
+    )
+}
+
+fn request_id_of(request: &Request) -> Option<String> {
+    let request_id = request.extensions().get::<RequestId>()?;
+
+    request_id
+        .header_value()
+        .to_str()
+        .ok()
+        .map(ToOwned::to_owned)
+}
+
+/// Renders error responses produced outside of handlers (extractor rejections,
+/// tower layers, etc.) with the error template, just like `AppError` does.
+pub(crate) async fn render_errors(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error()) {
+        return response;
+    }
+
+    // HTML is already rendered by `AppError`, and JSON is meant for API clients
+    let is_plain = match response.headers().get(header::CONTENT_TYPE) {
+        Some(content_type) => content_type.as_bytes().starts_with(b"text/plain"),
+        None => true,
+    };
+
+    if !is_plain {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+
+    let message = match axum::body::to_bytes(body, MAX_ERROR_BODY_SIZE).await {
+        Ok(bytes) if !bytes.is_empty() => String::from_utf8_lossy(&bytes).into_owned(),
+        _ => status
+            .canonical_reason()
+            .unwrap_or("Something went wrong")
+            .to_owned(),
+    };
+
+    let mut error = AppError::new(status, anyhow!(message));
+
+    error.retry_after = parts
+        .headers
+        .get(header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs);
+
+    let mut rendered = error.into_response();
+
+    // keep headers such as `Allow` for 405, but not the ones describing the old body
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            rendered.headers_mut().insert(name, value.clone());
+        }
+    }
+
+    rendered
+}
+
+pub(crate) async fn fallback() -> AppError {
+    AppError::with_status_404(anyhow!("This page doesn't exist."))
+}
+
+/// Compresses rendered pages and JSON responses only.
+pub(crate) fn compression() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(DefaultPredicate::new().and(is_html_or_json))
+}
+
+fn is_html_or_json(_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE) else {
+        return false;
+    };
+
+    let content_type = content_type.as_bytes();
+
+    content_type.starts_with(b"text/html") || content_type.starts_with(b"application/json")
+}
@@ -1,38 +1,268 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
 use anyhow::{anyhow, Result};
 use askama_axum::Template;
-use axum::extract::{Path, State};
-use axum::response::IntoResponse;
+use axum::extract::{DefaultBodyLimit, FromRef, Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::{get, post};
+use axum::Extension;
 use axum::{debug_handler, Form, Router};
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use tower_http::limit::RequestBodyLimitLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::trace::{self, TraceLayer};
 use tracing::{error, info, warn, Level};
 
+use crate::config::Config;
 use crate::database::breed::Breed;
-use crate::database::{Database, DatabaseRecord, PagingState, SetState};
+use crate::database::images::StoredImage;
+use crate::database::{Database, DatabaseRecord, DbError};
+use crate::jobs::{self, JobRunner, JobStatuses};
+use crate::storage::{self, ImageStorage};
+use crate::utils::id::DbId;
+use api::ActivityCache;
 use app_error::AppError;
-
+use cache_tags::CacheTags;
+use derpibooru::Derpibooru;
+use layout::Layout;
+use middleware::{ClientId, RateLimiter};
+use paging::PagingParameters;
+
+mod admin;
+mod api;
 mod app_error;
+mod assets;
+mod batch;
+mod cache_tags;
+mod changes;
+pub(crate) mod derpibooru;
+mod layout;
+mod metrics;
+mod middleware;
+mod paging;
+mod sitemap;
+
+#[derive(Debug, Clone, FromRef)]
+pub(crate) struct AppState {
+    pub(crate) database: Database,
+    pub(crate) config: Arc<Config>,
+    pub(crate) rate_limiter: RateLimiter,
+    pub(crate) jobs: JobStatuses,
+    pub(crate) activity: ActivityCache,
+    pub(crate) derpibooru: Derpibooru,
+    pub(crate) storage: ImageStorage,
+}
 
 pub async fn run() -> Result<()> {
-    let shared_state = Database::init().await?;
+    let config = Config::from_env()?;
+    layout::init(config.branding.clone());
+
+    let database = Database::init(&config.database).await?;
+
+    let mut job_runner = JobRunner::new();
+
+    let derpibooru = Derpibooru::new(config.gallery.derpibooru_url.clone())?;
+    let storage = ImageStorage::new(config.gallery.storage_dir.clone());
+
+    job_runner.register("purge", config.jobs.purge_interval, {
+        let database = database.clone();
+        let retention = config.jobs.purge_retention;
+        move || jobs::purge::purge(database.clone(), retention)
+    });
+
+    job_runner.register("images", config.jobs.images_interval, {
+        let database = database.clone();
+        let derpibooru = derpibooru.clone();
+        let storage = storage.clone();
+        let max_age = config.jobs.images_max_age;
+        move || {
+            jobs::images::refresh(
+                database.clone(),
+                derpibooru.clone(),
+                storage.clone(),
+                max_age,
+            )
+        }
+    });
+
+    job_runner.register("doctor", config.jobs.doctor_interval, {
+        let database = database.clone();
+        let repair = config.jobs.doctor_repair;
+        move || jobs::doctor::check(database.clone(), repair)
+    });
+
+    let http_client = reqwest::Client::builder()
+        .user_agent(concat!("MareWebsite/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+
+    job_runner.register_with_wakeup(
+        "cdn_purge",
+        config.jobs.cdn_purge_interval,
+        database.events().subscribe(),
+        {
+            let database = database.clone();
+            let client = http_client.clone();
+            let config = config.cdn.clone();
+            move || jobs::cdn::purge(database.clone(), client.clone(), config.clone())
+        },
+    );
+
+    job_runner.register_with_wakeup(
+        "webhooks",
+        config.jobs.webhooks_interval,
+        database.events().subscribe(),
+        {
+            let database = database.clone();
+            let config = config.webhooks.clone();
+            move || jobs::webhooks::deliver(database.clone(), http_client.clone(), config.clone())
+        },
+    );
+
+    let shared_state = AppState {
+        database,
+        rate_limiter: RateLimiter::new(config.rate_limit.clone()),
+        config: Arc::new(config),
+        jobs: job_runner.statuses(),
+        activity: ActivityCache::default(),
+        derpibooru,
+        storage,
+    };
 
     // build our application with a single route
     let layer = TraceLayer::new_for_http()
-        .make_span_with(trace::DefaultMakeSpan::new().level(Level::INFO))
+        .make_span_with(middleware::make_request_span)
         .on_response(trace::DefaultOnResponse::new().level(Level::INFO));
 
-    let routes = Router::new()
+    let http = &shared_state.config.http;
+
+    let pages = Router::new()
         .route("/", get(get_index))
         .route("/mares", get(get_mare_table))
         .route("/mares", post(post_mares))
+        .route("/mares/batch", post(batch::post_batch))
+        .route("/mares/page", get(paging::redirect_to_first_page))
+        .route("/mares/page/:page", get(paging::redirect_to_first_page))
+        .route(
+            "/mares/page/:page/:state",
+            get(paging::redirect_to_first_page),
+        )
         .route("/mares/page/:page/:state/:id", get(get_paged_mare_table))
         .route("/mares/:id", get(get_mare))
         .route("/mares/:id/delete", post(delete_mare))
         .route("/mares/:id/edit", post(edit_mare))
+        .route("/images/:mare_id/:image_id", get(get_image))
+        .route("/assets/manifest.json", get(assets::get_manifest))
+        .route("/assets/:name", get(assets::get_asset))
+        .route("/robots.txt", get(sitemap::get_robots))
+        .route("/sitemap.xml", get(sitemap::get_sitemap))
+        .route("/sitemap/:page", get(sitemap::get_sitemap_page))
+        .route_layer(axum::middleware::from_fn_with_state(
+            http.timeout,
+            middleware::timeout,
+        ));
+
+    // Waits for changes longer than other pages are allowed to take
+    let long_polls = Router::new()
+        .route("/mares/changes/poll", get(changes::poll_changes))
+        .route_layer(axum::middleware::from_fn_with_state(
+            http.long_poll_wait + http.timeout,
+            middleware::timeout,
+        ));
+
+    let api = Router::new()
+        .route("/api/v1/activity", get(api::get_activity))
+        .route("/api/v1/breeds", get(api::get_breeds))
+        .route_layer(axum::middleware::from_fn_with_state(
+            http.timeout,
+            middleware::timeout,
+        ));
+
+    let admin_pages = Router::new()
+        .route("/admin/backup", get(admin::get_backup))
+        .route("/admin/data", get(admin::get_data))
+        .route("/admin/data/repair", post(admin::repair_data))
+        .route("/admin/jobs", get(admin::get_jobs))
+        .route("/admin/metrics", get(metrics::get_metrics))
+        .route("/admin/storage", get(admin::get_storage))
+        .route("/admin/storage/page/:page", get(admin::get_storage_page))
+        .route(
+            "/admin/storage/images/:mare_id/:image_id/delete",
+            post(admin::delete_image),
+        )
+        .route("/admin/webhooks", get(admin::get_webhooks))
+        .route("/admin/webhooks", post(admin::post_webhooks))
+        .route("/admin/webhooks/:id/delete", post(admin::delete_webhook))
+        .route_layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            admin::require_admin,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            http.timeout,
+            middleware::timeout,
+        ));
+
+    // Backups are far larger than the forms of other pages
+    let restore = Router::new()
+        .route("/admin/restore", post(admin::post_restore))
+        .route_layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            admin::require_admin,
+        ))
+        .route_layer(axum::middleware::from_fn_with_state(
+            http.upstream_timeout,
+            middleware::timeout,
+        ))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(http.backup_limit));
+
+    let upstream_pages = Router::new()
         .route("/mares/:id/image", get(mare_image))
+        .route("/mares/:id/images", post(keep_image))
+        .route_layer(axum::middleware::from_fn_with_state(
+            http.upstream_timeout,
+            middleware::timeout,
+        ));
+
+    let routes = Router::new()
+        .merge(pages)
+        .merge(long_polls)
+        .merge(api)
+        .merge(admin_pages)
+        .merge(upstream_pages)
+        .fallback(middleware::fallback)
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(http.body_limit))
+        .merge(restore)
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            middleware::enforce_budget,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            middleware::rate_limit,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            shared_state.clone(),
+            middleware::maintenance,
+        ))
+        .layer(axum::middleware::from_fn(middleware::render_errors))
+        .layer(axum::middleware::from_fn(middleware::identify_client));
+
+    let routes = if http.compression {
+        routes.layer(middleware::compression())
+    } else {
+        routes
+    };
+
+    let routes = routes
+        .layer(axum::middleware::from_fn(middleware::correlate))
+        .layer(PropagateRequestIdLayer::x_request_id())
         .layer(layer)
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
         .with_state(shared_state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
@@ -43,75 +273,139 @@ pub async fn run() -> Result<()> {
 
     info!(ip = ?ip, port = ?port, "Bound IP address and port.");
 
-    axum::serve(listener, routes.into_make_service()).await?;
+    let served = axum::serve(
+        listener,
+        routes.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await;
+
+    job_runner.shutdown().await;
+
+    served?;
 
     Ok(())
 }
 
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for Ctrl+C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                error!("Failed to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutdown signal received, stopping the server");
+}
+
 #[derive(Debug, Template)]
 #[template(path = "index.askama.html")]
-struct IndexTemplate;
+struct IndexTemplate {
+    layout: Layout,
+}
 
-async fn get_index() -> Result<impl IntoResponse, AppError> {
-    let html = IndexTemplate;
+async fn get_index(State(config): State<Arc<Config>>) -> Result<Response, AppError> {
+    if config.listing.index_redirect {
+        return Ok(Redirect::to("/mares").into_response());
+    }
 
-    Ok(html)
+    let html = IndexTemplate {
+        layout: Layout::current(),
+    };
+
+    Ok(html.into_response())
 }
 
 #[derive(Debug, Template)]
 #[template(path = "mare_table.askama.html")]
 struct MareTableTemplate {
+    layout: Layout,
     ponies: Vec<DatabaseRecord>,
+    tags: HashMap<DbId, Vec<String>>,
+}
+
+impl MareTableTemplate {
+    fn tags_of(&self, pony: &DatabaseRecord) -> &[String] {
+        self.tags.get(&pony.id).map_or(&[], Vec::as_slice)
+    }
 }
 
-async fn get_mare_table(State(pool): State<Database>) -> Result<impl IntoResponse, AppError> {
-    let mare_records = pool.list().await?;
+async fn get_mare_table(
+    State(pool): State<Database>,
+    State(config): State<Arc<Config>>,
+) -> Result<impl IntoResponse, AppError> {
+    let mare_records = pool.records().list(config.listing.sort).await?;
+
+    let ids: Vec<DbId> = mare_records.iter().map(|record| record.id).collect();
+    let tags = pool.tags_by_mare(&ids).await?;
 
     let html = MareTableTemplate {
+        layout: Layout::current(),
         ponies: mare_records,
+        tags,
     };
 
-    Ok(html)
-}
-
-#[derive(Deserialize)]
-struct PagingParameters {
-    page: u32,
-    id: String,
-    state: PagingState,
+    Ok((CacheTags::collection(), html))
 }
 
 #[derive(Debug, Template)]
 #[template(path = "paged_mare_table.askama.html")]
 struct PagedMareTableTemplate {
+    layout: Layout,
     ponies: Vec<DatabaseRecord>,
-    first_id: Option<String>,
-    last_id: Option<String>,
+    first_id: Option<DbId>,
+    last_id: Option<DbId>,
     page: u32,
 }
 
-#[debug_handler]
+#[debug_handler(state = AppState)]
 async fn get_paged_mare_table(
     State(pool): State<Database>,
-    Path(params): Path<PagingParameters>,
+    State(config): State<Arc<Config>>,
+    params: PagingParameters,
 ) -> Result<impl IntoResponse, AppError> {
-    let mare_records = pool.get_paged_records(&params.id, params.state).await?;
+    let mare_records = pool
+        .records()
+        .paged(config.listing.sort, params.id, params.state)
+        .await?;
 
     let (first_id, last_id) = match (mare_records.first(), mare_records.last()) {
         (None, None) => (None, None),
-        (Some(first), Some(last)) => (Some(first.id.to_string()), Some(last.id.to_string())),
+        (Some(first), Some(last)) => (Some(first.id), Some(last.id)),
 
         _ => unreachable!(),
     };
 
     let html = PagedMareTableTemplate {
+        layout: Layout::current(),
         ponies: mare_records,
         first_id,
         last_id,
         page: params.page,
     };
 
-    Ok(html)
+    Ok((CacheTags::collection(), html))
 }
 
 #[derive(Deserialize, Debug)]
@@ -143,9 +437,9 @@ async fn post_mares(
 
 async fn delete_mare(
     State(pool): State<Database>,
-    Path(id): Path<String>,
+    Path(id): Path<DbId>,
 ) -> Result<impl IntoResponse, AppError> {
-    let Some(_) = pool.remove(&id).await? else {
+    let Some(_) = pool.remove(id).await? else {
         return Err(AppError::with_status_404(anyhow!(
             "Cannot find record with {id} id."
         )));
@@ -157,30 +451,39 @@ async fn delete_mare(
 #[derive(Debug, Template)]
 #[template(path = "get_mare.askama.html")]
 struct GetMareTemplate {
+    layout: Layout,
     name: String,
     breed: Breed,
-    id: String,
+    id: DbId,
     modified_at: DateTime<Utc>,
 }
 
 async fn get_mare(
     State(pool): State<Database>,
-    Path(id): Path<String>,
+    Path(id): Path<DbId>,
 ) -> Result<impl IntoResponse, AppError> {
-    let Some(mare) = pool.get(&id).await? else {
+    let Some(mare) = pool.records().get(id).await? else {
+        if pool.records().include_deleted().get(id).await?.is_some() {
+            return Err(AppError::new(
+                axum::http::StatusCode::GONE,
+                anyhow!("Record with {id} id was deleted."),
+            ));
+        }
+
         return Err(AppError::with_status_404(anyhow!(
             "Cannot find record with {id} id."
         )));
     };
 
     let html = GetMareTemplate {
+        layout: Layout::current(),
         name: mare.name,
         breed: mare.breed,
-        id: id.to_string(),
+        id,
         modified_at: mare.modified_at,
     };
 
-    Ok(html)
+    Ok((CacheTags::record(id), html))
 }
 
 #[derive(Debug, Deserialize)]
@@ -188,11 +491,16 @@ pub(crate) struct EditPonyForm {
     pub(crate) name: String,
     pub(crate) breed: Breed,
     pub(crate) modified_at: DateTime<Utc>,
+    /// Identifies this rendering of the edit form. Filled by `edit.js`, so it
+    /// is empty if scripts are disabled.
+    #[serde(default)]
+    pub(crate) nonce: String,
 }
 
 async fn edit_mare(
     State(pool): State<Database>,
-    Path(id): Path<String>,
+    Extension(client_id): Extension<ClientId>,
+    Path(id): Path<DbId>,
     form: Form<EditPonyForm>,
 ) -> Result<impl IntoResponse, AppError> {
     let pony_data = form.0;
@@ -205,10 +513,39 @@ async fn edit_mare(
     // sqlx feature to support Timestamp
 
     // html: timestamp (when sended) - hidden form input
-    let reason = match pool.set(&id, &pony_data).await? {
-        SetState::Success => return Ok(axum::response::Redirect::to("/mares")),
-        SetState::ModifiedAtConflict => "has already changed.",
-        SetState::RecordNotFound => "not found.",
+    let (code, reason) = match pool.set(id, &pony_data, &client_id.0).await {
+        Ok(()) => return Ok(axum::response::Redirect::to("/mares")),
+        Err(DbError::Conflict(_)) => match pool.records().last_edit(id).await? {
+            Some(edit) if !pony_data.nonce.is_empty() && edit.nonce == pony_data.nonce => {
+                let saved = pool.records().get(id).await?;
+
+                // The same form was submitted twice, e.g. by a double click or a page reload
+                if saved.is_some_and(|saved| {
+                    saved.name == pony_data.name && saved.breed == pony_data.breed
+                }) {
+                    return Ok(axum::response::Redirect::to("/mares"));
+                }
+
+                // E.g. the form was restored by the back button and changed
+                (
+                    StatusCode::CONFLICT,
+                    "has already been saved from this form. \
+                    Reload the page to change it again.",
+                )
+            }
+            Some(edit) if edit.editor == client_id.0 => (
+                StatusCode::CONFLICT,
+                "has already been saved from your other tab. \
+                Reload the page to see your changes.",
+            ),
+            _ => (
+                StatusCode::CONFLICT,
+                "has been changed by someone else since you opened it. \
+                Reload the page to see their changes.",
+            ),
+        },
+        Err(DbError::NotFound) => (StatusCode::NOT_FOUND, "not found."),
+        Err(err) => return Err(err.into()),
     };
 
     warn!("Cannot modify record with id = {id}, since record {reason}");
@@ -216,51 +553,27 @@ async fn edit_mare(
         format!("Unfortunately, it is impossible to save, since the mare's record {reason}");
 
     // TODO possible to direct user to the mare page with the data he specified
-    Err(AppError::new(
-        axum::http::StatusCode::CONFLICT,
-        anyhow!(message),
-    ))
-}
-
-#[derive(Debug, Deserialize)]
-struct ImageResponse {
-    images: Vec<Image>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Image {
-    id: i64,
-    representations: Representations,
-}
-
-#[derive(Debug, Deserialize)]
-struct Representations {
-    // large: String,
-    medium: String,
-    // small: String,
-}
-
-#[derive(Serialize)]
-struct MareQuery {
-    per_page: usize,
-    sf: String,
-    q: String,
+    Err(AppError::new(code, anyhow!(message)))
 }
 
 #[derive(Debug, Template)]
 #[template(path = "mare_image.askama.html")]
 struct MareImageTemplate {
+    layout: Layout,
     name: String,
-    pony_id: String,
+    pony_id: DbId,
     image_id: i64,
     image: String,
+    /// Images kept in the gallery, newest first.
+    kept: Vec<StoredImage>,
 }
 
 async fn mare_image(
     State(pool): State<Database>,
-    Path(id): Path<String>,
+    State(derpibooru): State<Derpibooru>,
+    Path(id): Path<DbId>,
 ) -> Result<impl IntoResponse, AppError> {
-    let name = match pool.get(&id).await? {
+    let name = match pool.records().get(id).await? {
         Some(record) => record.name,
         None => {
             return Err(AppError::with_status_404(anyhow!(
@@ -269,26 +582,17 @@ async fn mare_image(
         }
     };
 
-    let client = reqwest::Client::builder()
-        .user_agent(concat!(
-            "MareWebsite",
-            env!("CARGO_PKG_VERSION"),
-            "https://github.com/nitkach",
-        ))
-        .build()?;
-
-    let url = "https://derpibooru.org/api/v1/json/search/images";
-    let tags = format!("score.gte:100, {name}, pony, mare, !irl");
-    let query = [("per_page", "1"), ("sf", "random"), ("q", &tags)];
-    let request = client.get(url).query(&query);
-
-    info!(url = url, query = ?query, "Request created, sending...");
-    let response = request.send().await?;
-
-    let response = match response.error_for_status() {
-        Ok(response) => response,
+    let image = match derpibooru.random_image(&name).await {
+        Ok(Some(image)) => image,
+        Ok(None) => {
+            // TODO
+            return Err(AppError::new(
+                axum::http::StatusCode::BAD_GATEWAY,
+                anyhow!("Cannot find images by \"{name}\" name."),
+            ));
+        }
         Err(err) => {
-            warn!("An error came from the server when trying to get an image");
+            warn!("An error came from the server when trying to get an image: {err}");
             let code = if let Some(code) = err.status() {
                 axum::http::StatusCode::from_u16(code.as_u16())?
             } else {
@@ -302,22 +606,106 @@ async fn mare_image(
         }
     };
 
-    let mut response = response.json::<ImageResponse>().await?;
-
-    let Some(image) = response.images.pop() else {
-        // TODO
-        return Err(AppError::new(
-            axum::http::StatusCode::BAD_GATEWAY,
-            anyhow!("Cannot find images by \"{name}\" name."),
-        ));
-    };
+    let kept = pool.records().images_of(id).await?;
 
     let html = MareImageTemplate {
+        layout: Layout::current(),
         name,
         pony_id: id,
         image_id: image.id,
         image: image.representations.medium,
+        kept,
     };
 
     Ok(html)
 }
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct KeepImageForm {
+    image_id: i64,
+}
+
+/// Adds an image to the gallery of a mare and stores its file. The metadata is taken
+/// from derpibooru rather than from the form, so only real images end up in galleries.
+async fn keep_image(
+    State(pool): State<Database>,
+    State(derpibooru): State<Derpibooru>,
+    State(storage): State<ImageStorage>,
+    Path(id): Path<DbId>,
+    Form(form): Form<KeepImageForm>,
+) -> Result<impl IntoResponse, AppError> {
+    if pool.records().get(id).await?.is_none() {
+        return Err(AppError::with_status_404(anyhow!(
+            "Cannot find record with {id} id."
+        )));
+    }
+
+    let image = derpibooru
+        .image(form.image_id)
+        .await
+        .map_err(|err| AppError::new(StatusCode::BAD_GATEWAY, err.into()))?
+        .ok_or_else(|| {
+            AppError::with_status_404(anyhow!(
+                "Image {} doesn't exist on derpibooru.",
+                form.image_id
+            ))
+        })?;
+
+    let bytes = derpibooru
+        .download(&image.representations.medium)
+        .await
+        .map_err(|err| AppError::new(StatusCode::BAD_GATEWAY, err.into()))?;
+
+    // Recorded before the file is stored, so the images job doesn't take it for unused
+    pool.record_image(
+        id,
+        image.id,
+        &image.representations.medium,
+        &derpibooru.source_url(image.id),
+    )
+    .await?;
+
+    storage
+        .save(image.id, &bytes)
+        .await
+        .map_err(|err| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, err.into()))?;
+
+    Ok(Redirect::to(&format!("/mares/{id}/image")))
+}
+
+/// File of an image kept in the gallery of a mare.
+async fn get_image(
+    State(pool): State<Database>,
+    State(storage): State<ImageStorage>,
+    Path((mare_id, image_id)): Path<(DbId, i64)>,
+) -> Result<impl IntoResponse, AppError> {
+    let not_found = || {
+        AppError::with_status_404(anyhow!(
+            "Image {image_id} is not kept in the gallery of {mare_id}."
+        ))
+    };
+
+    if pool.records().get(mare_id).await?.is_none() {
+        return Err(not_found());
+    }
+
+    let image = pool
+        .records()
+        .image(mare_id, image_id)
+        .await?
+        .ok_or_else(not_found)?;
+
+    let bytes = storage
+        .read(image_id)
+        .await
+        .map_err(|err| AppError::new(StatusCode::INTERNAL_SERVER_ERROR, err.into()))?
+        .ok_or_else(not_found)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, storage::content_type(&image.url)),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        bytes,
+    ))
+}
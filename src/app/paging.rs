@@ -0,0 +1,88 @@
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use tracing::warn;
+
+use super::app_error::AppError;
+use crate::{database::PagingState, utils::id::DbId};
+
+pub(crate) const FIRST_PAGE_URL: &str = "/mares/page/1/next/0";
+
+/// Path parameters of `/mares/page/:page/:state/:id`.
+///
+/// An unknown `state` is rejected with 400 listing the valid values, while
+/// malformed page numbers are redirected to the first page.
+#[derive(Debug)]
+pub(crate) struct PagingParameters {
+    pub(crate) page: u32,
+    pub(crate) state: PagingState,
+    /// `None` for `0`, the id of the first page.
+    pub(crate) id: Option<DbId>,
+}
+
+pub(crate) enum PagingRejection {
+    UnknownState(anyhow::Error),
+    InvalidPage,
+}
+
+impl IntoResponse for PagingRejection {
+    fn into_response(self) -> Response {
+        match self {
+            PagingRejection::UnknownState(err) => {
+                AppError::new(StatusCode::BAD_REQUEST, err).into_response()
+            }
+            PagingRejection::InvalidPage => Redirect::to(FIRST_PAGE_URL).into_response(),
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PagingParameters
+where
+    S: Send + Sync,
+{
+    type Rejection = PagingRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path((page, paging_state, id)) =
+            Path::<(String, String, String)>::from_request_parts(parts, state)
+                .await
+                .map_err(|err| {
+                    warn!("Cannot extract paging parameters: {err}");
+                    PagingRejection::InvalidPage
+                })?;
+
+        let page = match page.parse::<u32>() {
+            Ok(page) if page > 0 => page,
+            _ => {
+                warn!("Invalid page number {page:?}, redirecting to the first page");
+                return Err(PagingRejection::InvalidPage);
+            }
+        };
+
+        let state = paging_state
+            .parse()
+            .map_err(PagingRejection::UnknownState)?;
+
+        let id = match id.as_str() {
+            "0" => None,
+            id => match id.parse::<DbId>() {
+                Ok(id) => Some(id),
+                Err(err) => {
+                    warn!("Invalid paging id, redirecting to the first page: {err}");
+                    return Err(PagingRejection::InvalidPage);
+                }
+            },
+        };
+
+        Ok(Self { page, state, id })
+    }
+}
+
+/// Old or incomplete paging URLs, e.g. `/mares/page/2`, lead to the first page.
+pub(crate) async fn redirect_to_first_page() -> Redirect {
+    Redirect::permanent(FIRST_PAGE_URL)
+}
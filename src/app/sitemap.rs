@@ -0,0 +1,164 @@
+use std::fmt::Write;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use super::app_error::AppError;
+use super::cache_tags::CacheTags;
+use crate::config::Config;
+use crate::database::Database;
+
+/// Number of records per sitemap file. Search engines accept up to 50 000 URLs.
+const PAGE_SIZE: i64 = 10_000;
+
+/// Pages that don't depend on records, listed in the first sitemap file.
+const STATIC_PAGES: [&str; 2] = ["/", "/mares"];
+
+/// A sitemap of all records, or an index of sitemap files if there are too many of them.
+pub(crate) async fn get_sitemap(
+    State(pool): State<Database>,
+    State(config): State<Arc<Config>>,
+) -> Result<impl IntoResponse, AppError> {
+    let pages = pool.records().last_modified_by_page(PAGE_SIZE).await?;
+
+    if pages.len() <= 1 {
+        return urlset(&pool, &config, 1).await;
+    }
+
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#,
+        "\n",
+    ));
+
+    for (index, last_modified) in pages.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            "<sitemap><loc>{}</loc><lastmod>{}</lastmod></sitemap>",
+            escape(&absolute(&config, &format!("/sitemap/{}", index + 1))),
+            w3c_datetime(last_modified),
+        );
+    }
+
+    xml.push_str("</sitemapindex>\n");
+
+    Ok((
+        CacheTags::collection(),
+        [(header::CONTENT_TYPE, "application/xml")],
+        xml,
+    ))
+}
+
+pub(crate) async fn get_sitemap_page(
+    State(pool): State<Database>,
+    State(config): State<Arc<Config>>,
+    Path(page): Path<i64>,
+) -> Result<impl IntoResponse, AppError> {
+    urlset(&pool, &config, page).await
+}
+
+async fn urlset(
+    pool: &Database,
+    config: &Config,
+    page: i64,
+) -> Result<(CacheTags, [(header::HeaderName, &'static str); 1], String), AppError> {
+    if page < 1 {
+        return Err(AppError::with_status_404(anyhow!(
+            "Sitemap {page} doesn't exist."
+        )));
+    }
+
+    let records = pool
+        .records()
+        .slice((page - 1) * PAGE_SIZE, PAGE_SIZE)
+        .await?;
+
+    if records.is_empty() && page > 1 {
+        return Err(AppError::with_status_404(anyhow!(
+            "Sitemap {page} doesn't exist."
+        )));
+    }
+
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        "\n",
+        r#"<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">"#,
+        "\n",
+    ));
+
+    if page == 1 {
+        for path in STATIC_PAGES {
+            let _ = writeln!(
+                xml,
+                "<url><loc>{}</loc></url>",
+                escape(&absolute(config, path))
+            );
+        }
+    }
+
+    for record in &records {
+        let _ = writeln!(
+            xml,
+            "<url><loc>{}</loc><lastmod>{}</lastmod></url>",
+            escape(&absolute(config, &format!("/mares/{}", record.id))),
+            w3c_datetime(&record.modified_at),
+        );
+    }
+
+    xml.push_str("</urlset>\n");
+
+    Ok((
+        CacheTags::collection(),
+        [(header::CONTENT_TYPE, "application/xml")],
+        xml,
+    ))
+}
+
+pub(crate) async fn get_robots(State(config): State<Arc<Config>>) -> impl IntoResponse {
+    let seo = &config.seo;
+
+    let mut robots = String::from("User-agent: *\n");
+
+    if seo.indexing {
+        for path in &seo.disallow {
+            let _ = writeln!(robots, "Disallow: {path}");
+        }
+    } else {
+        robots.push_str("Disallow: /\n");
+    }
+
+    let _ = writeln!(robots, "\nSitemap: {}", absolute(&config, "/sitemap.xml"));
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        robots,
+    )
+}
+
+/// Keeps the path of the public URL, in case the site is served under a prefix.
+fn absolute(config: &Config, path: &str) -> String {
+    let mut url = config.seo.public_url.clone();
+    let base = url.path().trim_end_matches('/').to_owned();
+    url.set_path(&format!("{base}{path}"));
+
+    url.to_string()
+}
+
+fn w3c_datetime(datetime: &DateTime<Utc>) -> String {
+    datetime.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,441 @@
+//! Versioned format of exports and backups, read back by `import` and `restore`.
+//!
+//! Every change of the format bumps [`SCHEMA_VERSION`] and adds a converter
+//! to [`UPGRADES`], so archives written by older versions can still be imported.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::database::breed::Breed;
+use crate::database::events::MareEventKind;
+use crate::database::tags::MAX_TAG_LENGTH;
+use crate::utils::id::DbId;
+
+/// Version of the archives written by this build.
+pub(crate) const SCHEMA_VERSION: u64 = 5;
+
+/// `UPGRADES[n]` converts an archive of version `n + 1` to version `n + 2`.
+const UPGRADES: [fn(Value) -> Result<Value>; SCHEMA_VERSION as usize - 1] =
+    [upgrade_v1, upgrade_v2, upgrade_v3, upgrade_v4];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Archive {
+    pub(crate) schema_version: u64,
+    /// Unknown for archives converted from version 1.
+    pub(crate) exported_at: Option<DateTime<Utc>>,
+    pub(crate) records: Vec<ArchivedRecord>,
+    pub(crate) tags: Vec<ArchivedTag>,
+    pub(crate) images: Vec<ArchivedImage>,
+    /// History of changes, only included in backups.
+    pub(crate) events: Vec<ArchivedEvent>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArchivedRecord {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) breed: Breed,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) modified_at: DateTime<Utc>,
+    /// Only deleted records in backups have it.
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArchivedTag {
+    pub(crate) mare_id: String,
+    pub(crate) tag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArchivedImage {
+    pub(crate) mare_id: String,
+    pub(crate) image_id: i64,
+    pub(crate) url: String,
+    pub(crate) source_url: String,
+    pub(crate) fetched_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArchivedEvent {
+    pub(crate) id: i64,
+    pub(crate) mare_id: String,
+    pub(crate) kind: MareEventKind,
+    pub(crate) payload: Value,
+    pub(crate) request_id: Option<String>,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+impl Archive {
+    /// Reads an archive of any known version, converting it to the current one.
+    pub(crate) fn read(reader: impl Read) -> Result<Self> {
+        let mut value: Value = serde_json::from_reader(reader).context("Archive is not JSON")?;
+
+        let version = schema_version(&value)?;
+        if version == 0 || version > SCHEMA_VERSION {
+            bail!(
+                "Archive has schema version {version}, \
+                only versions from 1 to {SCHEMA_VERSION} are supported"
+            );
+        }
+
+        for upgrade in &UPGRADES[version as usize - 1..] {
+            value = upgrade(value)?;
+        }
+
+        let mut archive: Self = serde_json::from_value(value).context("Archive is malformed")?;
+        archive.canonicalize()?;
+        archive.validate()?;
+
+        Ok(archive)
+    }
+
+    /// Rewrites ids the way [`DbId`] writes them, e.g. lowercase ULIDs in uppercase,
+    /// as records are looked up by the canonical form of their id.
+    fn canonicalize(&mut self) -> Result<()> {
+        for record in &mut self.records {
+            record.id = DbId::from_str(&record.id)
+                .with_context(|| format!("Record \"{}\" has invalid id", record.name))?
+                .to_string();
+        }
+
+        // References that are not ids are reported by `validate`, as they can't resolve
+        let references = (self.tags.iter_mut().map(|tag| &mut tag.mare_id))
+            .chain(self.images.iter_mut().map(|image| &mut image.mare_id))
+            .chain(self.events.iter_mut().map(|event| &mut event.mare_id));
+
+        for mare_id in references {
+            if let Ok(id) = DbId::from_str(mare_id) {
+                *mare_id = id.to_string();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut ids = HashSet::new();
+
+        for record in &self.records {
+            if !ids.insert(record.id.as_str()) {
+                bail!("Record id {} appears more than once", record.id);
+            }
+        }
+
+        let references = (self.tags.iter().map(|tag| ("Tag", &tag.mare_id)))
+            .chain(self.images.iter().map(|image| ("Image", &image.mare_id)))
+            .chain(self.events.iter().map(|event| ("Event", &event.mare_id)));
+
+        for (row, mare_id) in references {
+            if !ids.contains(mare_id.as_str()) {
+                bail!("{row} of {mare_id} refers to a record that is not in the archive");
+            }
+        }
+
+        for tag in &self.tags {
+            if tag.tag.is_empty() || tag.tag.chars().count() > MAX_TAG_LENGTH {
+                bail!(
+                    "Tag \"{}\" of {} must be from 1 to {MAX_TAG_LENGTH} characters long",
+                    tag.tag,
+                    tag.mare_id
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Version 1 had no envelope and wasn't marked with a version.
+fn schema_version(value: &Value) -> Result<u64> {
+    if value.is_array() {
+        return Ok(1);
+    }
+
+    value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| anyhow!("Archive has no schema version"))
+}
+
+/// Version 1 was a bare array of records.
+fn upgrade_v1(value: Value) -> Result<Value> {
+    Ok(json!({
+        "schema_version": 2,
+        "exported_at": null,
+        "records": value,
+        "tags": [],
+        "images": [],
+    }))
+}
+
+/// Version 2 had no history.
+fn upgrade_v2(mut value: Value) -> Result<Value> {
+    value["schema_version"] = json!(3);
+    value["events"] = json!([]);
+
+    Ok(value)
+}
+
+/// Version 3 had no deleted records.
+fn upgrade_v3(mut value: Value) -> Result<Value> {
+    value["schema_version"] = json!(4);
+
+    if let Some(records) = value["records"].as_array_mut() {
+        for record in records {
+            record["deleted_at"] = Value::Null;
+        }
+    }
+
+    Ok(value)
+}
+
+/// Version 4 had no creation time. It is taken from the first event of the
+/// record, like in the migration that added it, or else from `modified_at`.
+fn upgrade_v4(mut value: Value) -> Result<Value> {
+    value["schema_version"] = json!(5);
+
+    let mut first_events: HashMap<String, DateTime<Utc>> = HashMap::new();
+
+    for event in value["events"].as_array().into_iter().flatten() {
+        let (Some(mare_id), Some(created_at)) = (
+            event["mare_id"].as_str(),
+            event["created_at"]
+                .as_str()
+                .and_then(|created_at| created_at.parse::<DateTime<Utc>>().ok()),
+        ) else {
+            continue;
+        };
+
+        first_events
+            .entry(mare_id.to_owned())
+            .and_modify(|first| *first = (*first).min(created_at))
+            .or_insert(created_at);
+    }
+
+    if let Some(records) = value["records"].as_array_mut() {
+        for record in records {
+            let created_at = record["id"]
+                .as_str()
+                .and_then(|id| first_events.get(id))
+                .map_or_else(|| record["modified_at"].clone(), |first| json!(first));
+
+            record["created_at"] = created_at;
+        }
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "01J00000000000000000000001";
+    const OTHER_ID: &str = "01J00000000000000000000002";
+
+    fn read(value: Value) -> Result<Archive> {
+        Archive::read(value.to_string().as_bytes())
+    }
+
+    fn time(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn reads_v1_bare_array() {
+        let archive = read(json!([{
+            "id": ID.to_lowercase(),
+            "name": "Applejack",
+            "breed": "earth",
+            "modified_at": "2024-01-02T00:00:00Z",
+        }]))
+        .unwrap();
+
+        assert_eq!(archive.schema_version, SCHEMA_VERSION);
+        assert_eq!(archive.exported_at, None);
+        assert!(archive.tags.is_empty());
+        assert!(archive.images.is_empty());
+        assert!(archive.events.is_empty());
+
+        let [record] = &archive.records[..] else {
+            panic!("expected one record, got {:?}", archive.records);
+        };
+        assert_eq!(record.id, ID);
+        assert_eq!(record.name, "Applejack");
+        assert_eq!(record.breed, Breed::Earth);
+        assert_eq!(record.created_at, time("2024-01-02T00:00:00Z"));
+        assert_eq!(record.modified_at, time("2024-01-02T00:00:00Z"));
+        assert_eq!(record.deleted_at, None);
+    }
+
+    #[test]
+    fn reads_v2_without_events() {
+        let archive = read(json!({
+            "schema_version": 2,
+            "exported_at": "2024-02-01T00:00:00Z",
+            "records": [{
+                "id": ID,
+                "name": "Fluttershy",
+                "breed": "pegasus",
+                "modified_at": "2024-01-02T00:00:00Z",
+            }],
+            "tags": [{ "mare_id": ID, "tag": "shy" }],
+            "images": [{
+                "mare_id": ID,
+                "image_id": 1,
+                "url": "https://example.com/1.png",
+                "source_url": "https://example.com/images/1",
+                "fetched_at": "2024-01-03T00:00:00Z",
+            }],
+        }))
+        .unwrap();
+
+        assert_eq!(archive.exported_at, Some(time("2024-02-01T00:00:00Z")));
+        assert!(archive.events.is_empty());
+        assert_eq!(archive.tags.len(), 1);
+        assert_eq!(archive.images.len(), 1);
+        assert_eq!(archive.records[0].deleted_at, None);
+    }
+
+    #[test]
+    fn reads_v3_with_first_event_as_creation_time() {
+        let event = |id: i64, kind: &str, created_at: &str| {
+            json!({
+                "id": id,
+                "mare_id": ID,
+                "kind": kind,
+                "payload": {},
+                "request_id": null,
+                "created_at": created_at,
+            })
+        };
+
+        let archive = read(json!({
+            "schema_version": 3,
+            "exported_at": "2024-02-01T00:00:00Z",
+            "records": [{
+                "id": ID,
+                "name": "Rarity",
+                "breed": "unicorn",
+                "modified_at": "2024-01-05T00:00:00Z",
+            }],
+            "tags": [],
+            "images": [],
+            "events": [
+                event(2, "updated", "2024-01-05T00:00:00Z"),
+                event(1, "created", "2024-01-01T00:00:00Z"),
+            ],
+        }))
+        .unwrap();
+
+        let record = &archive.records[0];
+        assert_eq!(record.created_at, time("2024-01-01T00:00:00Z"));
+        assert_eq!(record.deleted_at, None);
+        assert_eq!(archive.events.len(), 2);
+        assert_eq!(archive.events[1].kind, MareEventKind::Created);
+    }
+
+    #[test]
+    fn reads_v4_deleted_record() {
+        let archive = read(json!({
+            "schema_version": 4,
+            "exported_at": "2024-02-01T00:00:00Z",
+            "records": [{
+                "id": ID,
+                "name": "Rainbow Dash",
+                "breed": "pegasus",
+                "modified_at": "2024-01-05T00:00:00Z",
+                "deleted_at": "2024-01-06T00:00:00Z",
+            }],
+            "tags": [],
+            "images": [],
+            "events": [],
+        }))
+        .unwrap();
+
+        let record = &archive.records[0];
+        assert_eq!(record.created_at, time("2024-01-05T00:00:00Z"));
+        assert_eq!(record.deleted_at, Some(time("2024-01-06T00:00:00Z")));
+    }
+
+    #[test]
+    fn round_trips_current_version() {
+        let snapshot = json!({
+            "schema_version": SCHEMA_VERSION,
+            "exported_at": "2024-02-01T00:00:00Z",
+            "records": [
+                {
+                    "id": ID,
+                    "name": "Twilight Sparkle",
+                    "breed": "unicorn",
+                    "created_at": "2024-01-01T00:00:00Z",
+                    "modified_at": "2024-01-02T00:00:00Z",
+                    "deleted_at": null,
+                },
+                {
+                    "id": OTHER_ID,
+                    "name": "Pinkie Pie",
+                    "breed": "earth",
+                    "created_at": "2024-01-03T00:00:00Z",
+                    "modified_at": "2024-01-04T00:00:00Z",
+                    "deleted_at": "2024-01-05T00:00:00Z",
+                },
+            ],
+            "tags": [{ "mare_id": ID, "tag": "books" }],
+            "images": [{
+                "mare_id": OTHER_ID,
+                "image_id": 7,
+                "url": "https://example.com/7.png",
+                "source_url": "https://example.com/images/7",
+                "fetched_at": "2024-01-03T00:00:00Z",
+            }],
+            "events": [{
+                "id": 1,
+                "mare_id": ID,
+                "kind": "created",
+                "payload": { "name": "Twilight Sparkle" },
+                "request_id": "abc",
+                "created_at": "2024-01-01T00:00:00Z",
+            }],
+        });
+
+        let archive = read(snapshot.clone()).unwrap();
+        let written = serde_json::to_value(&archive).unwrap();
+        assert_eq!(written, snapshot);
+
+        let reread = read(written).unwrap();
+        assert_eq!(serde_json::to_value(&reread).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn rejects_invalid_archives() {
+        let record = |id: &str| {
+            json!({
+                "id": id,
+                "name": "Derpy",
+                "breed": "pegasus",
+                "modified_at": "2024-01-01T00:00:00Z",
+            })
+        };
+
+        assert!(read(json!({ "schema_version": SCHEMA_VERSION + 1 })).is_err());
+        assert!(read(json!({ "records": [] })).is_err());
+        assert!(read(json!([record(ID), record(&ID.to_lowercase())])).is_err());
+        assert!(read(json!([record("not an id")])).is_err());
+        assert!(read(json!({
+            "schema_version": 2,
+            "exported_at": null,
+            "records": [record(ID)],
+            "tags": [{ "mare_id": OTHER_ID, "tag": "muffins" }],
+            "images": [],
+        }))
+        .is_err());
+    }
+}
@@ -1,3 +0,0 @@
-fn main() {
-    // let database = mare_website::
-}
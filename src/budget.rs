@@ -0,0 +1,159 @@
+//! Time budgets of requests from internal clients with strict SLAs.
+//!
+//! A trusted client may send `x-request-budget-ms` with the number of milliseconds
+//! it is willing to wait. The request then runs inside [`scope`], which cancels it
+//! with everything it is waiting for, database queries and upstream calls alike,
+//! once the budget is spent. Spans created meanwhile are recorded by [`BudgetLayer`],
+//! so the client learns which steps finished in time and which ones were cut short.
+
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tracing::{span, Subscriber};
+use tracing_subscriber::{layer::Context, registry::LookupSpan, Layer};
+
+pub(crate) const REQUEST_BUDGET_HEADER: &str = "x-request-budget-ms";
+
+/// Steps beyond this are not recorded, so a request that loops doesn't grow its report forever.
+const MAX_STEPS: usize = 256;
+
+tokio::task_local! {
+    static BUDGET: Arc<Budget>;
+}
+
+#[derive(Debug)]
+struct Budget {
+    started_at: Instant,
+    deadline: Instant,
+    steps: Mutex<Vec<Step>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Step {
+    /// Target and name of the span, e.g. `mare_website::database::records::get`.
+    name: String,
+    /// Since the start of the request.
+    started_ms: u64,
+    /// `None` if the step was still running when the budget ran out.
+    elapsed_ms: Option<u64>,
+}
+
+/// Partial diagnostics of a request that ran out of its budget.
+#[derive(Debug, Serialize)]
+pub(crate) struct Exhausted {
+    budget_ms: u64,
+    elapsed_ms: u64,
+    steps: Vec<Step>,
+}
+
+impl Budget {
+    fn millis_since_start(&self) -> u64 {
+        u64::try_from(self.started_at.elapsed().as_millis()).unwrap_or(u64::MAX)
+    }
+
+    /// Returns the index of the new step, if it is recorded.
+    fn start_step(&self, name: String) -> Option<usize> {
+        let mut steps = self.steps.lock().unwrap();
+
+        if steps.len() >= MAX_STEPS {
+            return None;
+        }
+
+        steps.push(Step {
+            name,
+            started_ms: self.millis_since_start(),
+            elapsed_ms: None,
+        });
+
+        Some(steps.len() - 1)
+    }
+
+    fn finish_step(&self, index: usize) {
+        let now = self.millis_since_start();
+
+        if let Some(step) = self.steps.lock().unwrap().get_mut(index) {
+            step.elapsed_ms = Some(now.saturating_sub(step.started_ms));
+        }
+    }
+
+    fn exhausted(&self) -> Exhausted {
+        let budget = self.deadline.duration_since(self.started_at);
+
+        Exhausted {
+            budget_ms: u64::try_from(budget.as_millis()).unwrap_or(u64::MAX),
+            elapsed_ms: self.millis_since_start(),
+            steps: self.steps.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Runs `future` until it completes or `budget` is spent, whichever comes first.
+pub(crate) async fn scope<F: Future>(budget: Duration, future: F) -> Result<F::Output, Exhausted> {
+    let started_at = Instant::now();
+    let budget = Arc::new(Budget {
+        started_at,
+        deadline: started_at + budget,
+        steps: Mutex::default(),
+    });
+
+    BUDGET
+        .scope(budget.clone(), async {
+            // Stays alive until the report is taken, so unfinished steps are not
+            // mistaken for finished ones when their spans are dropped
+            let future = std::pin::pin!(future);
+
+            tokio::select! {
+                output = future => Ok(output),
+                () = tokio::time::sleep_until(budget.deadline.into()) => Err(budget.exhausted()),
+            }
+        })
+        .await
+}
+
+/// Records spans created by requests that run with a budget, for [`Exhausted`] reports.
+pub(crate) struct BudgetLayer;
+
+/// Kept in the extensions of a recorded span.
+struct Tracked {
+    budget: Arc<Budget>,
+    index: usize,
+}
+
+impl<S> Layer<S> for BudgetLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attributes: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Ok(budget) = BUDGET.try_with(Arc::clone) else {
+            return;
+        };
+
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let metadata = attributes.metadata();
+
+        if let Some(index) =
+            budget.start_step(format!("{}::{}", metadata.target(), metadata.name()))
+        {
+            span.extensions_mut().insert(Tracked { budget, index });
+        }
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+
+        let extensions = span.extensions();
+
+        if let Some(tracked) = extensions.get::<Tracked>() {
+            tracked.budget.finish_step(tracked.index);
+        }
+    }
+}
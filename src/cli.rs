@@ -0,0 +1,376 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use tracing::info;
+
+use crate::app::{self, AddPonyForm};
+use crate::archive::Archive;
+use crate::config::Config;
+use crate::database::breed::Breed;
+use crate::database::Database;
+use crate::jobs;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Website about mares")]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run the web server (default)
+    Serve,
+    /// Apply database migrations and exit
+    Migrate,
+    /// Insert sample mares into the database
+    Seed,
+    /// Write all records to stdout, in the order of `LISTING_SORT`
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+    },
+    /// Insert records from a JSON export, keeping the existing ones
+    Import {
+        /// Path to the export, `-` to read from stdin
+        path: PathBuf,
+    },
+    /// Write all data with the history of changes to a file
+    Backup {
+        /// Path to the backup, `-` to write to stdout
+        path: PathBuf,
+    },
+    /// Restore a backup into an empty database
+    Restore {
+        /// Path to the backup, `-` to read from stdin
+        path: PathBuf,
+    },
+    /// Save or load named snapshots of all data, for development
+    Fixtures {
+        #[command(subcommand)]
+        command: FixturesCommand,
+    },
+    /// Replace record ids of unknown format with ULIDs and rewrite others in canonical form
+    MigrateIds,
+    /// Check that the configuration is valid and the database is reachable
+    Doctor {
+        /// Also scan the data for anomalies
+        #[arg(long)]
+        data: bool,
+        /// Repair the anomalies that can be repaired automatically
+        #[arg(long, requires = "data")]
+        fix: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum FixturesCommand {
+    /// Write all data with the history of changes to a fixture
+    Save {
+        /// Name of the fixture, e.g. `many-tags`
+        name: String,
+    },
+    /// Replace all data with a fixture
+    Load {
+        /// Name of the fixture, e.g. `many-tags`
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// Records only
+    Csv,
+    /// Records, tags and image metadata, readable by `import`
+    Json,
+}
+
+/// Directory with fixtures, unless `FIXTURES_DIR` is set.
+const DEFAULT_FIXTURES_DIR: &str = "fixtures";
+
+const SEED_MARES: &[(&str, Breed)] = &[
+    ("Twilight Sparkle", Breed::Unicorn),
+    ("Rainbow Dash", Breed::Pegasus),
+    ("Applejack", Breed::Earth),
+    ("Fluttershy", Breed::Pegasus),
+    ("Rarity", Breed::Unicorn),
+    ("Pinkie Pie", Breed::Earth),
+    ("Starlight Glimmer", Breed::Unicorn),
+    ("Derpy Hooves", Breed::Pegasus),
+    ("Maud Pie", Breed::Earth),
+    ("Lyra Heartstrings", Breed::Unicorn),
+];
+
+impl Cli {
+    pub(crate) async fn run(self) -> Result<()> {
+        match self.command.unwrap_or(Command::Serve) {
+            Command::Serve => app::run().await,
+            Command::Migrate => {
+                let config = Config::from_env().context("Configuration is invalid")?;
+
+                Database::connect(&config.database).await?.migrate().await?;
+
+                Ok(())
+            }
+            Command::Seed => seed().await,
+            Command::Export { format } => export(format).await,
+            Command::Import { path } => import(path).await,
+            Command::Backup { path } => backup(path).await,
+            Command::Restore { path } => restore(path).await,
+            Command::Fixtures { command } => match command {
+                FixturesCommand::Save { name } => save_fixture(&name).await,
+                FixturesCommand::Load { name } => load_fixture(&name).await,
+            },
+            Command::MigrateIds => {
+                let updated = init_database().await?.update_to_ulid().await?;
+
+                eprintln!("Updated {updated} record ids");
+
+                Ok(())
+            }
+            Command::Doctor { data, fix } => doctor(data, fix).await,
+        }
+    }
+}
+
+/// Connects with the configuration from the environment and applies pending migrations.
+async fn init_database() -> Result<Database> {
+    let config = Config::from_env().context("Configuration is invalid")?;
+
+    Ok(Database::init(&config.database).await?)
+}
+
+async fn seed() -> Result<()> {
+    let database = init_database().await?;
+
+    for &(name, breed) in SEED_MARES {
+        let form = AddPonyForm {
+            name: name.to_owned(),
+            breed,
+        };
+
+        database.add(&form).await?;
+    }
+
+    info!("Inserted {} sample mares", SEED_MARES.len());
+
+    Ok(())
+}
+
+async fn export(format: ExportFormat) -> Result<()> {
+    let config = Config::from_env().context("Configuration is invalid")?;
+    let database = Database::init(&config.database).await?;
+
+    let mut stdout = std::io::stdout().lock();
+
+    match format {
+        ExportFormat::Csv => {
+            let records = database.records().list(config.listing.sort).await?;
+            let mut writer = csv::Writer::from_writer(&mut stdout);
+
+            for record in &records {
+                writer.serialize(record)?;
+            }
+
+            writer.flush()?;
+        }
+        ExportFormat::Json => {
+            let archive = database.snapshot(false).await?;
+
+            serde_json::to_writer_pretty(&mut stdout, &archive)?;
+            writeln!(stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn import(path: PathBuf) -> Result<()> {
+    let archive = read_archive(&path)?;
+
+    let database = init_database().await?;
+    let summary = database.import(&archive).await?;
+
+    eprintln!(
+        "Imported {} of {} records, {} of {} tags, {} of {} images",
+        summary.records,
+        archive.records.len(),
+        summary.tags,
+        archive.tags.len(),
+        summary.images,
+        archive.images.len(),
+    );
+
+    Ok(())
+}
+
+async fn backup(path: PathBuf) -> Result<()> {
+    let database = init_database().await?;
+    let archive = database.snapshot(true).await?;
+
+    write_archive(&path, &archive)?;
+
+    eprintln!(
+        "Backed up {} records, {} tags, {} images and {} events",
+        archive.records.len(),
+        archive.tags.len(),
+        archive.images.len(),
+        archive.events.len(),
+    );
+
+    Ok(())
+}
+
+async fn restore(path: PathBuf) -> Result<()> {
+    let archive = read_archive(&path)?;
+
+    let database = init_database().await?;
+    let summary = database.restore(&archive).await?;
+
+    eprintln!(
+        "Restored {} records, {} tags, {} images and {} events",
+        summary.records, summary.tags, summary.images, summary.events,
+    );
+
+    Ok(())
+}
+
+async fn save_fixture(name: &str) -> Result<()> {
+    let path = fixture_path(name)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Cannot create {}", dir.display()))?;
+    }
+
+    let database = init_database().await?;
+    let archive = database.snapshot(true).await?;
+
+    write_archive(&path, &archive)?;
+
+    eprintln!(
+        "Saved {} records, {} tags, {} images and {} events to {}",
+        archive.records.len(),
+        archive.tags.len(),
+        archive.images.len(),
+        archive.events.len(),
+        path.display(),
+    );
+
+    Ok(())
+}
+
+async fn load_fixture(name: &str) -> Result<()> {
+    let path = fixture_path(name)?;
+
+    if !path.exists() {
+        bail!("Fixture \"{name}\" doesn't exist at {}", path.display());
+    }
+
+    let archive = read_archive(&path)?;
+
+    let database = init_database().await?;
+    let summary = database.replace(&archive).await?;
+
+    eprintln!(
+        "Loaded {} records, {} tags, {} images and {} events from {}",
+        summary.records,
+        summary.tags,
+        summary.images,
+        summary.events,
+        path.display(),
+    );
+
+    Ok(())
+}
+
+/// Names are restricted, so a fixture can't be written outside of its directory.
+fn fixture_path(name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_');
+
+    if !valid {
+        bail!("Fixture name \"{name}\" may only contain letters, digits, `-` and `_`");
+    }
+
+    let dir = std::env::var_os("FIXTURES_DIR")
+        .map_or_else(|| PathBuf::from(DEFAULT_FIXTURES_DIR), PathBuf::from);
+
+    Ok(dir.join(format!("{name}.json")))
+}
+
+/// Writes an archive to `path`, or to stdout if it is `-`.
+fn write_archive(path: &Path, archive: &Archive) -> Result<()> {
+    let mut writer: Box<dyn Write> = if path.as_os_str() == "-" {
+        Box::new(std::io::stdout().lock())
+    } else {
+        let file =
+            File::create(path).with_context(|| format!("Cannot create {}", path.display()))?;
+        Box::new(std::io::BufWriter::new(file))
+    };
+
+    serde_json::to_writer_pretty(&mut writer, archive)?;
+    writeln!(writer)?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Reads an archive from `path`, or from stdin if it is `-`.
+fn read_archive(path: &Path) -> Result<Archive> {
+    if path.as_os_str() == "-" {
+        return Archive::read(std::io::stdin().lock());
+    }
+
+    let file = File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+
+    Archive::read(std::io::BufReader::new(file))
+}
+
+async fn doctor(data: bool, fix: bool) -> Result<()> {
+    let config = Config::from_env().context("Configuration is invalid")?;
+    println!("Configuration is valid");
+
+    let database = Database::connect(&config.database)
+        .await
+        .context("Database is unreachable")?;
+    println!("Database is reachable");
+
+    if !data {
+        return Ok(());
+    }
+
+    let mut anomalies = database.check_data().await?;
+
+    if fix && anomalies.iter().any(|anomaly| anomaly.kind.is_fixable()) {
+        let repaired = database.repair_data().await?;
+        println!("Repaired {repaired} rows");
+
+        anomalies = database.check_data().await?;
+    }
+
+    for anomaly in &anomalies {
+        let fixable = if anomaly.kind.is_fixable() {
+            "fixable"
+        } else {
+            "manual"
+        };
+
+        println!(
+            "{} [{fixable}] {}: {}",
+            anomaly.mare_id, anomaly.kind, anomaly.detail
+        );
+    }
+
+    println!("{}", jobs::doctor::summary(&anomalies));
+
+    if !anomalies.is_empty() {
+        bail!("Data has anomalies");
+    }
+
+    Ok(())
+}
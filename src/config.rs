@@ -0,0 +1,388 @@
+use std::{net::IpAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{anyhow, Result};
+use ipnet::IpNet;
+use url::Url;
+
+use crate::{database::records::SortOrder, utils::id::IdFormat};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) admin: AdminConfig,
+    pub(crate) branding: BrandingConfig,
+    pub(crate) cdn: CdnConfig,
+    pub(crate) database: DatabaseConfig,
+    pub(crate) gallery: GalleryConfig,
+    pub(crate) http: HttpConfig,
+    pub(crate) internal: InternalConfig,
+    pub(crate) jobs: JobsConfig,
+    pub(crate) listing: ListingConfig,
+    pub(crate) maintenance: MaintenanceConfig,
+    pub(crate) rate_limit: RateLimitConfig,
+    pub(crate) seo: SeoConfig,
+    pub(crate) webhooks: WebhooksConfig,
+}
+
+#[derive(Clone)]
+pub(crate) struct AdminConfig {
+    /// Password of the `admin` user for HTTP basic auth.
+    /// Admin pages are disabled if it is not set.
+    pub(crate) password: Option<String>,
+}
+
+impl std::fmt::Debug for AdminConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AdminConfig")
+            .field("password", &self.password.as_ref().map(|_| "<hidden>"))
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct BrandingConfig {
+    pub(crate) site_name: String,
+    pub(crate) logo_url: String,
+    pub(crate) footer: Option<String>,
+    /// CSS hex color, e.g. `#6f42c1`.
+    pub(crate) accent_color: String,
+}
+
+impl Default for BrandingConfig {
+    fn default() -> Self {
+        Self {
+            site_name: "MareWebsite".to_owned(),
+            logo_url: "https://derpicdn.net/img/2022/3/4/2818722/thumb.png".to_owned(),
+            footer: None,
+            accent_color: "#6f42c1".to_owned(),
+        }
+    }
+}
+
+/// API of a CDN that caches pages, purged after every change.
+#[derive(Clone)]
+pub(crate) struct CdnConfig {
+    /// Purging is disabled if `None`.
+    pub(crate) purge_url: Option<Url>,
+    pub(crate) purge_token: Option<String>,
+    pub(crate) provider: CdnProvider,
+    pub(crate) timeout: Duration,
+}
+
+impl std::fmt::Debug for CdnConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CdnConfig")
+            .field("purge_url", &self.purge_url)
+            .field(
+                "purge_token",
+                &self.purge_token.as_ref().map(|_| "<hidden>"),
+            )
+            .field("provider", &self.provider)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Decides the format of purge requests.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum CdnProvider {
+    /// `POST /service/{id}/purge` with surrogate keys.
+    #[default]
+    Fastly,
+    /// `POST /zones/{id}/purge_cache` with cache tags.
+    Cloudflare,
+}
+
+impl CdnProvider {
+    /// Values that can be parsed into a `CdnProvider`.
+    pub(crate) const VALUES: [&'static str; 2] = ["fastly", "cloudflare"];
+}
+
+impl FromStr for CdnProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fastly" => Ok(CdnProvider::Fastly),
+            "cloudflare" => Ok(CdnProvider::Cloudflare),
+            _ => Err(anyhow!(
+                "Unknown CDN provider \"{s}\". Valid values: {}.",
+                Self::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct DatabaseConfig {
+    /// Format of the ids of new records and webhooks.
+    pub(crate) id_format: IdFormat,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct GalleryConfig {
+    /// Derpibooru or another booru with the same API, e.g. a local mirror.
+    pub(crate) derpibooru_url: Url,
+    /// Directory with the files of kept images.
+    pub(crate) storage_dir: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpConfig {
+    pub(crate) compression: bool,
+    pub(crate) body_limit: usize,
+    /// Largest backup accepted by `/admin/restore`.
+    pub(crate) backup_limit: usize,
+    /// Timeout for handlers that only talk to the database.
+    pub(crate) timeout: Duration,
+    /// Timeout for handlers that wait for external services, e.g. derpibooru.
+    pub(crate) upstream_timeout: Duration,
+    /// How long `/mares/changes/poll` waits for a change before answering with none.
+    pub(crate) long_poll_wait: Duration,
+}
+
+/// Clients inside the infrastructure, e.g. services that consume the API.
+#[derive(Clone)]
+pub(crate) struct InternalConfig {
+    /// Sent by internal clients in `x-internal-token`. They may limit the time
+    /// their requests take with `x-request-budget-ms`, which is ignored if not set.
+    pub(crate) token: Option<String>,
+}
+
+impl std::fmt::Debug for InternalConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InternalConfig")
+            .field("token", &self.token.as_ref().map(|_| "<hidden>"))
+            .finish()
+    }
+}
+
+/// Intervals of background jobs. A zero interval disables the job.
+#[derive(Debug, Clone)]
+pub(crate) struct JobsConfig {
+    pub(crate) purge_interval: Duration,
+    /// How long deleted records are kept before they are removed for good.
+    pub(crate) purge_retention: Duration,
+    pub(crate) images_interval: Duration,
+    /// Age after which stored images are downloaded again.
+    pub(crate) images_max_age: Duration,
+    pub(crate) doctor_interval: Duration,
+    /// Whether the doctor job repairs the anomalies it finds, or only reports them.
+    pub(crate) doctor_repair: bool,
+    /// Webhooks are also delivered right after every change,
+    /// so this only matters for retries.
+    pub(crate) webhooks_interval: Duration,
+    /// Like webhooks, the CDN is purged right after every change.
+    pub(crate) cdn_purge_interval: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ListingConfig {
+    pub(crate) sort: SortOrder,
+    /// Whether `/` redirects to the mare table instead of showing the landing page.
+    pub(crate) index_redirect: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MaintenanceConfig {
+    pub(crate) enabled: bool,
+    pub(crate) retry_after: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimitConfig {
+    /// Maximum number of requests a single client can make per `window`.
+    /// `0` disables rate limiting.
+    pub(crate) requests: u32,
+    pub(crate) window: Duration,
+    /// Reverse proxies whose `x-forwarded-for` is trusted to name the client.
+    /// Without them, every client behind a proxy shares the limit of the proxy.
+    pub(crate) trusted_proxies: Vec<IpNet>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SeoConfig {
+    /// Address the site is reachable at, used for absolute URLs in sitemaps.
+    pub(crate) public_url: Url,
+    /// Whether robots are allowed to index the site at all.
+    pub(crate) indexing: bool,
+    /// Paths that robots shouldn't index.
+    pub(crate) disallow: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct WebhooksConfig {
+    /// Number of attempts after which a delivery is given up.
+    pub(crate) max_attempts: i32,
+    pub(crate) timeout: Duration,
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Result<Self> {
+        let admin = AdminConfig {
+            password: std::env::var("ADMIN_PASSWORD")
+                .ok()
+                .filter(|password| !password.is_empty()),
+        };
+
+        let default_branding = BrandingConfig::default();
+        let branding = BrandingConfig {
+            site_name: env_or("SITE_NAME", default_branding.site_name)?,
+            logo_url: env_or("SITE_LOGO_URL", default_branding.logo_url)?,
+            footer: std::env::var("SITE_FOOTER")
+                .ok()
+                .filter(|footer| !footer.is_empty()),
+            accent_color: env_or("SITE_ACCENT_COLOR", default_branding.accent_color)?,
+        };
+
+        if !is_hex_color(&branding.accent_color) {
+            return Err(anyhow!(
+                "Invalid value {:?} of SITE_ACCENT_COLOR variable: expected a color like #6f42c1",
+                branding.accent_color
+            ));
+        }
+
+        let cdn = CdnConfig {
+            purge_url: std::env::var("CDN_PURGE_URL")
+                .ok()
+                .filter(|url| !url.is_empty())
+                .map(|url| Url::parse(&url))
+                .transpose()
+                .map_err(|err| anyhow!("Invalid value of CDN_PURGE_URL variable: {err}"))?,
+            purge_token: std::env::var("CDN_PURGE_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+            provider: env_or("CDN_PROVIDER", CdnProvider::default())?,
+            timeout: Duration::from_secs(env_or("CDN_PURGE_TIMEOUT_SECS", 10)?),
+        };
+
+        let database = DatabaseConfig {
+            id_format: env_or("ID_FORMAT", IdFormat::default())?,
+        };
+
+        let gallery = GalleryConfig {
+            derpibooru_url: env_or("DERPIBOORU_URL", Url::parse("https://derpibooru.org")?)?,
+            storage_dir: env_or("IMAGE_STORAGE_DIR", PathBuf::from("data/images"))?,
+        };
+
+        let http = HttpConfig {
+            compression: env_or("HTTP_COMPRESSION", true)?,
+            body_limit: env_or("HTTP_BODY_LIMIT_BYTES", 64 * 1024)?,
+            backup_limit: env_or("HTTP_BACKUP_LIMIT_BYTES", 64 * 1024 * 1024)?,
+            timeout: Duration::from_secs(env_or("HTTP_TIMEOUT_SECS", 10)?),
+            upstream_timeout: Duration::from_secs(env_or("HTTP_UPSTREAM_TIMEOUT_SECS", 30)?),
+            long_poll_wait: Duration::from_secs(env_or("HTTP_LONG_POLL_WAIT_SECS", 25)?),
+        };
+
+        let internal = InternalConfig {
+            token: std::env::var("INTERNAL_API_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty()),
+        };
+
+        let jobs = JobsConfig {
+            purge_interval: Duration::from_secs(env_or("JOB_PURGE_INTERVAL_SECS", 60 * 60)?),
+            purge_retention: env_days_or("JOB_PURGE_RETENTION_DAYS", 30)?,
+            images_interval: Duration::from_secs(env_or("JOB_IMAGES_INTERVAL_SECS", 6 * 60 * 60)?),
+            images_max_age: env_days_or("JOB_IMAGES_MAX_AGE_DAYS", 30)?,
+            doctor_interval: Duration::from_secs(env_or("JOB_DOCTOR_INTERVAL_SECS", 60 * 60)?),
+            doctor_repair: env_or("JOB_DOCTOR_REPAIR", false)?,
+            webhooks_interval: Duration::from_secs(env_or("JOB_WEBHOOKS_INTERVAL_SECS", 30)?),
+            cdn_purge_interval: Duration::from_secs(env_or("JOB_CDN_PURGE_INTERVAL_SECS", 60)?),
+        };
+
+        let listing = ListingConfig {
+            sort: env_or("LISTING_SORT", SortOrder::default())?,
+            index_redirect: env_or("LISTING_INDEX_REDIRECT", false)?,
+        };
+
+        let maintenance = MaintenanceConfig {
+            enabled: env_or("MAINTENANCE_MODE", false)?,
+            retry_after: Duration::from_secs(env_or("MAINTENANCE_RETRY_AFTER_SECS", 300)?),
+        };
+
+        let rate_limit = RateLimitConfig {
+            requests: env_or("RATE_LIMIT_REQUESTS", 120)?,
+            window: Duration::from_secs(env_or("RATE_LIMIT_WINDOW_SECS", 60)?),
+            trusted_proxies: env_or("RATE_LIMIT_TRUSTED_PROXIES", String::new())?
+                .split(',')
+                .map(str::trim)
+                .filter(|network| !network.is_empty())
+                .map(parse_network)
+                .collect::<Result<_>>()?,
+        };
+
+        let seo = SeoConfig {
+            public_url: env_or("PUBLIC_URL", Url::parse("http://localhost:3000")?)?,
+            indexing: env_or("ROBOTS_INDEXING", true)?,
+            disallow: env_or("ROBOTS_DISALLOW", "/admin/".to_owned())?
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        };
+
+        let webhooks = WebhooksConfig {
+            max_attempts: env_or("WEBHOOK_MAX_ATTEMPTS", 8)?,
+            timeout: Duration::from_secs(env_or("WEBHOOK_TIMEOUT_SECS", 10)?),
+        };
+
+        Ok(Self {
+            admin,
+            branding,
+            cdn,
+            database,
+            gallery,
+            http,
+            internal,
+            jobs,
+            listing,
+            maintenance,
+            rate_limit,
+            seo,
+            webhooks,
+        })
+    }
+}
+
+/// Reads and parses an environment variable, falling back to `default`
+/// if the variable is not set.
+fn env_or<T>(key: &str, default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map_err(|err| anyhow!("Invalid value {value:?} of {key} variable: {err}")),
+        Err(std::env::VarError::NotPresent) => Ok(default),
+        Err(err) => Err(anyhow!("Cannot read {key} variable: {err}")),
+    }
+}
+
+/// A duration given in whole days.
+fn env_days_or(key: &str, default: u64) -> Result<Duration> {
+    let days = env_or(key, default)?;
+
+    days.checked_mul(24 * 60 * 60)
+        .map(Duration::from_secs)
+        .ok_or_else(|| anyhow!("Invalid value {days:?} of {key} variable: too many days"))
+}
+
+/// A network like `172.16.0.0/12`, or a single address.
+fn parse_network(network: &str) -> Result<IpNet> {
+    network
+        .parse()
+        .or_else(|_| network.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|err| {
+            anyhow!("Invalid value {network:?} in RATE_LIMIT_TRUSTED_PROXIES variable: {err}")
+        })
+}
+
+/// `#rgb` or `#rrggbb`, the only colors that are safe to put into a stylesheet as is.
+fn is_hex_color(color: &str) -> bool {
+    color.strip_prefix('#').is_some_and(|hex| {
+        matches!(hex.len(), 3 | 6) && hex.chars().all(|char| char.is_ascii_hexdigit())
+    })
+}
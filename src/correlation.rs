@@ -0,0 +1,41 @@
+//! Keeps background work traceable back to the request that started it.
+//!
+//! Every request runs inside [`scope`] with its `x-request-id`. Background jobs
+//! should store the id of the request that enqueued them and run inside [`scope`]
+//! too, so their logs and outbound HTTP calls made through [`outbound`] carry the
+//! same id.
+
+use std::future::Future;
+
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Runs `future` with `request_id` available through [`request_id`].
+pub(crate) async fn scope<F: Future>(request_id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(request_id, future).await
+}
+
+/// Runs `future` on behalf of the request with `request_id`, if it is known,
+/// e.g. when a background job processes work enqueued by that request.
+pub(crate) async fn resume<F: Future>(request_id: Option<String>, future: F) -> F::Output {
+    match request_id {
+        Some(request_id) => scope(request_id, future).await,
+        None => future.await,
+    }
+}
+
+/// Id of the request the current task is working on, if any.
+pub(crate) fn request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Adds the request id of the current task to an outbound request.
+pub(crate) fn outbound(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match request_id() {
+        Some(request_id) => request.header(REQUEST_ID_HEADER, request_id),
+        None => request,
+    }
+}
@@ -0,0 +1,398 @@
+use chrono::Utc;
+use sqlx::{Postgres, Transaction};
+use tracing::{info, instrument, Level};
+
+use super::events::{self, MareEventKind};
+use super::{Database, DatabaseRecord, DbError, DbResult};
+use crate::archive::{
+    Archive, ArchivedEvent, ArchivedImage, ArchivedRecord, ArchivedTag, SCHEMA_VERSION,
+};
+use crate::utils::id::DbId;
+
+/// Number of rows that were inserted by an import or a restore.
+#[derive(Debug, Default)]
+pub(crate) struct ImportSummary {
+    pub(crate) records: u64,
+    pub(crate) tags: u64,
+    pub(crate) images: u64,
+    pub(crate) events: u64,
+}
+
+impl Database {
+    /// Consistent snapshot of the data. Backups (`full`) also include
+    /// deleted records and the history of changes.
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn snapshot(&self, full: bool) -> DbResult<Archive> {
+        let records = if full {
+            self.records().include_deleted()
+        } else {
+            self.records()
+        };
+
+        let mut transaction = self.pool.begin().await?;
+
+        sqlx::query!("set transaction isolation level repeatable read, read only")
+            .execute(&mut *transaction)
+            .await?;
+
+        let records: Vec<ArchivedRecord> = records
+            .stored(&mut *transaction)
+            .await?
+            .into_iter()
+            .map(|record| ArchivedRecord {
+                id: record.id,
+                name: record.name,
+                breed: record.breed,
+                created_at: record.created_at,
+                modified_at: record.modified_at,
+                deleted_at: record.deleted_at,
+            })
+            .collect();
+
+        let ids: Vec<String> = records.iter().map(|record| record.id.clone()).collect();
+
+        let tags = sqlx::query_as!(
+            ArchivedTag,
+            r#"
+            select mare_id, tag
+            from mare_tags
+            where mare_id = any($1)
+            order by mare_id, tag
+            "#,
+            &ids
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        let images = sqlx::query_as!(
+            ArchivedImage,
+            r#"
+            select mare_id, image_id, url, source_url, fetched_at
+            from mare_images
+            where mare_id = any($1)
+            order by mare_id, image_id
+            "#,
+            &ids
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        let events = if full {
+            sqlx::query!(
+                r#"
+                select id, mare_id, kind, payload, request_id, created_at
+                from mare_events
+                order by id
+                "#
+            )
+            .fetch_all(&mut *transaction)
+            .await?
+            .into_iter()
+            .map(|row| {
+                Ok(ArchivedEvent {
+                    id: row.id,
+                    mare_id: row.mare_id,
+                    kind: row.kind.parse().map_err(DbError::Other)?,
+                    payload: row.payload,
+                    request_id: row.request_id,
+                    created_at: row.created_at,
+                })
+            })
+            .collect::<DbResult<_>>()?
+        } else {
+            Vec::new()
+        };
+
+        transaction.commit().await?;
+
+        Ok(Archive {
+            schema_version: SCHEMA_VERSION,
+            exported_at: Some(Utc::now()),
+            records,
+            tags,
+            images,
+            events,
+        })
+    }
+
+    /// Inserts the contents of `archive` in a single transaction.
+    /// Rows that already exist are kept as is. The history of the archive is
+    /// not imported, instead every inserted record is recorded as created,
+    /// so webhooks, the CDN and long polls learn about it.
+    #[instrument(level = Level::INFO, skip_all, fields(schema_version = archive.schema_version))]
+    pub(crate) async fn import(&self, archive: &Archive) -> DbResult<ImportSummary> {
+        let mut transaction = self.pool.begin().await?;
+
+        let inserted = insert_records(&mut transaction, &archive.records).await?;
+
+        let mut last_event_id = None;
+        for record in &inserted {
+            last_event_id =
+                Some(events::record(&mut transaction, MareEventKind::Created, record).await?);
+        }
+
+        let summary = ImportSummary {
+            records: inserted.len() as u64,
+            tags: insert_tags(&mut transaction, &archive.tags).await?,
+            images: insert_images(&mut transaction, &archive.images).await?,
+            events: inserted.len() as u64,
+        };
+
+        transaction.commit().await?;
+
+        if let Some(event_id) = last_event_id {
+            self.events.publish(event_id);
+        }
+
+        info!(?summary, "Imported archive");
+
+        Ok(summary)
+    }
+
+    /// Restores a backup with its history in a single transaction.
+    /// Fails with [`DbError::Conflict`] unless the database is empty.
+    #[instrument(level = Level::INFO, skip_all, fields(schema_version = archive.schema_version))]
+    pub(crate) async fn restore(&self, archive: &Archive) -> DbResult<ImportSummary> {
+        let mut transaction = self.pool.begin().await?;
+
+        lock_tables(&mut transaction).await?;
+
+        let is_empty = sqlx::query_scalar!(
+            r#"
+            select not (
+                exists (select from mares)
+                or exists (select from mare_tags)
+                or exists (select from mare_images)
+                or exists (select from mare_events)
+            ) as "is_empty!"
+            "#
+        )
+        .fetch_one(&mut *transaction)
+        .await?;
+
+        if !is_empty {
+            return Err(DbError::Conflict(
+                "Backups can only be restored into an empty database".to_owned(),
+            ));
+        }
+
+        let summary = insert_all(&mut transaction, archive, EventIds::Keep).await?;
+
+        transaction.commit().await?;
+
+        if let Some(event_id) = archive.events.iter().map(|event| event.id).max() {
+            self.events.publish(event_id);
+        }
+
+        info!(?summary, "Restored backup");
+
+        Ok(summary)
+    }
+
+    /// Replaces all data with a backup in a single transaction. Pending webhook
+    /// deliveries are dropped with the history they belong to. Events of the
+    /// backup get ids after the replaced ones, so a running server, which only
+    /// notices ids greater than those it has seen, keeps following changes.
+    #[instrument(level = Level::INFO, skip_all, fields(schema_version = archive.schema_version))]
+    pub(crate) async fn replace(&self, archive: &Archive) -> DbResult<ImportSummary> {
+        let mut transaction = self.pool.begin().await?;
+
+        lock_tables(&mut transaction).await?;
+
+        sqlx::query!("truncate table mare_tags, mare_images, mare_events, mares cascade")
+            .execute(&mut *transaction)
+            .await?;
+
+        let summary = insert_all(&mut transaction, archive, EventIds::Renumber).await?;
+
+        transaction.commit().await?;
+
+        self.events.publish(self.latest_event_id().await?);
+
+        info!(?summary, "Replaced data with backup");
+
+        Ok(summary)
+    }
+}
+
+/// Keeps concurrent writes out until the transaction is committed.
+async fn lock_tables(transaction: &mut Transaction<'_, Postgres>) -> DbResult<()> {
+    sqlx::query!("lock table mares, mare_tags, mare_images, mare_events in exclusive mode")
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(())
+}
+
+/// How archived events are inserted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventIds {
+    /// Events keep the ids from the archive.
+    Keep,
+    /// Events are moved after the last id ever issued, keeping their order.
+    Renumber,
+}
+
+async fn insert_all(
+    transaction: &mut Transaction<'_, Postgres>,
+    archive: &Archive,
+    event_ids: EventIds,
+) -> DbResult<ImportSummary> {
+    Ok(ImportSummary {
+        records: insert_records(transaction, &archive.records).await?.len() as u64,
+        tags: insert_tags(transaction, &archive.tags).await?,
+        images: insert_images(transaction, &archive.images).await?,
+        events: insert_events(transaction, &archive.events, event_ids).await?,
+    })
+}
+
+/// Returns the records that were inserted, skipping the ones that already exist.
+async fn insert_records(
+    transaction: &mut Transaction<'_, Postgres>,
+    records: &[ArchivedRecord],
+) -> DbResult<Vec<DatabaseRecord>> {
+    let mut inserted = Vec::new();
+
+    for record in records {
+        let breed: i32 = record.breed.into();
+
+        let row = sqlx::query_as!(
+            DatabaseRecord,
+            r#"
+            insert into mares (id, name, breed, created_at, modified_at, deleted_at)
+            values ($1, $2, $3, $4, $5, $6)
+            on conflict (id) do nothing
+            returning id as "id: DbId", name, breed, modified_at
+            "#,
+            record.id,
+            record.name,
+            breed,
+            record.created_at,
+            record.modified_at,
+            record.deleted_at
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        inserted.extend(row);
+    }
+
+    Ok(inserted)
+}
+
+async fn insert_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    tags: &[ArchivedTag],
+) -> DbResult<u64> {
+    let mut inserted = 0;
+
+    for tag in tags {
+        inserted += sqlx::query!(
+            r#"
+            insert into mare_tags (mare_id, tag)
+            values ($1, $2)
+            on conflict (mare_id, tag) do nothing
+            "#,
+            tag.mare_id,
+            tag.tag
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected();
+    }
+
+    Ok(inserted)
+}
+
+async fn insert_images(
+    transaction: &mut Transaction<'_, Postgres>,
+    images: &[ArchivedImage],
+) -> DbResult<u64> {
+    let mut inserted = 0;
+
+    for image in images {
+        inserted += sqlx::query!(
+            r#"
+            insert into mare_images (mare_id, image_id, url, source_url, fetched_at)
+            values ($1, $2, $3, $4, $5)
+            on conflict (mare_id, image_id) do nothing
+            "#,
+            image.mare_id,
+            image.image_id,
+            image.url,
+            image.source_url,
+            image.fetched_at
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected();
+    }
+
+    Ok(inserted)
+}
+
+/// Ids are never reused: the sequence is moved past the inserted events, but
+/// never back. Jobs that follow events start over from the first inserted one.
+async fn insert_events(
+    transaction: &mut Transaction<'_, Postgres>,
+    events: &[ArchivedEvent],
+    event_ids: EventIds,
+) -> DbResult<u64> {
+    // Also counts ids of events that were removed since, e.g. by a replace
+    let last_issued = sqlx::query_scalar!(
+        r#"
+        select greatest(
+            (select coalesce(max(id), 0) from mare_events),
+            (select case when is_called then last_value else last_value - 1 end
+             from mare_events_id_seq)
+        ) as "last_issued!"
+        "#
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    let shift = match event_ids {
+        EventIds::Keep => 0,
+        EventIds::Renumber => last_issued,
+    };
+
+    let mut inserted = 0;
+
+    for event in events {
+        inserted += sqlx::query!(
+            r#"
+            insert into mare_events (id, mare_id, kind, payload, request_id, created_at)
+            values ($1, $2, $3, $4, $5, $6)
+            "#,
+            event.id + shift,
+            event.mare_id,
+            event.kind.as_str(),
+            event.payload,
+            event.request_id,
+            event.created_at
+        )
+        .execute(&mut **transaction)
+        .await?
+        .rows_affected();
+    }
+
+    sqlx::query!(
+        r#"
+        select setval(
+            pg_get_serial_sequence('mare_events', 'id'),
+            greatest(coalesce(max(id), 0), $1) + 1,
+            false
+        )
+        from mare_events
+        "#,
+        last_issued
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    sqlx::query!("update job_cursors set event_id = $1", shift)
+        .execute(&mut **transaction)
+        .await?;
+
+    Ok(inserted)
+}
@@ -0,0 +1,119 @@
+use tracing::{info, instrument, Level};
+
+use super::{
+    breed::Breed,
+    events::{self, MareEventKind},
+    Database, DatabaseRecord, DbResult,
+};
+use crate::utils::id::DbId;
+
+#[derive(Debug, Clone)]
+pub(crate) enum BatchOperation {
+    Delete,
+    SetBreed(Breed),
+    AddTag(String),
+}
+
+impl Database {
+    /// Applies `operation` to all records with the given ids in a single transaction.
+    /// Returns the affected records; ids that don't exist are skipped.
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn batch(
+        &self,
+        ids: &[DbId],
+        operation: &BatchOperation,
+    ) -> DbResult<Vec<DatabaseRecord>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let records = self
+            .records()
+            .get_many_for_update(&mut transaction, ids)
+            .await?;
+
+        let found: Vec<DbId> = records.iter().map(|record| record.id).collect();
+
+        let (records, kind) = match operation {
+            BatchOperation::Delete => {
+                sqlx::query!(
+                    r#"
+                    update mares
+                    set deleted_at = now(), edited_by = null, edit_nonce = null
+                    where id = any($1)
+                    "#,
+                    &found as &[DbId]
+                )
+                .execute(&mut *transaction)
+                .await?;
+
+                (records, MareEventKind::Deleted)
+            }
+            BatchOperation::SetBreed(breed) => {
+                let breed: i32 = (*breed).into();
+
+                let records = sqlx::query_as!(
+                    DatabaseRecord,
+                    r#"
+                    update mares
+                    set breed = $2, modified_at = now(), edited_by = null, edit_nonce = null
+                    where id = any($1)
+                    returning id as "id!: DbId", name as "name!", breed as "breed!", modified_at as "modified_at!"
+                    "#,
+                    &found as &[DbId],
+                    breed
+                )
+                .fetch_all(&mut *transaction)
+                .await?;
+
+                (records, MareEventKind::Updated)
+            }
+            BatchOperation::AddTag(tag) => {
+                sqlx::query!(
+                    r#"
+                    insert into mare_tags (mare_id, tag)
+                    select mare_id, $2
+                    from unnest($1::varchar[]) as mare_id
+                    on conflict do nothing
+                    "#,
+                    &found as &[DbId],
+                    tag
+                )
+                .execute(&mut *transaction)
+                .await?;
+
+                let records = sqlx::query_as!(
+                    DatabaseRecord,
+                    r#"
+                    update mares
+                    set modified_at = now(), edited_by = null, edit_nonce = null
+                    where id = any($1)
+                    returning id as "id!: DbId", name as "name!", breed as "breed!", modified_at as "modified_at!"
+                    "#,
+                    &found as &[DbId]
+                )
+                .fetch_all(&mut *transaction)
+                .await?;
+
+                (records, MareEventKind::Updated)
+            }
+        };
+
+        let mut last_event_id = None;
+        for record in &records {
+            last_event_id = Some(events::record(&mut transaction, kind, record).await?);
+        }
+
+        transaction.commit().await?;
+
+        if let Some(event_id) = last_event_id {
+            self.events.publish(event_id);
+        }
+
+        info!(
+            "Batch operation affected {} of {} requested records.",
+            records.len(),
+            ids.len()
+        );
+
+        Ok(records)
+    }
+}
@@ -1,9 +1,9 @@
 use std::fmt::Display;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 #[repr(i32)]
-#[derive(Clone, Copy, Debug, Deserialize, PartialEq, sqlx::Type)]
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize, sqlx::Type)]
 #[serde(rename_all = "snake_case")]
 pub(crate) enum Breed {
     Earth = 0,
@@ -11,6 +11,36 @@ pub(crate) enum Breed {
     Unicorn = 2,
 }
 
+impl Breed {
+    pub(crate) const ALL: [Breed; 3] = [Breed::Earth, Breed::Pegasus, Breed::Unicorn];
+
+    /// Value used in forms and JSON.
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Breed::Earth => "earth",
+            Breed::Pegasus => "pegasus",
+            Breed::Unicorn => "unicorn",
+        }
+    }
+
+    pub(crate) fn icon(self) -> &'static str {
+        match self {
+            Breed::Earth => "🌾",
+            Breed::Pegasus => "🪽",
+            Breed::Unicorn => "🦄",
+        }
+    }
+
+    /// CSS color of the breed.
+    pub(crate) fn color(self) -> &'static str {
+        match self {
+            Breed::Earth => "#d4a373",
+            Breed::Pegasus => "#74b3e8",
+            Breed::Unicorn => "#c49be0",
+        }
+    }
+}
+
 impl Display for Breed {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let breed = match self {
@@ -0,0 +1,185 @@
+use std::fmt::Display;
+
+use chrono::Utc;
+use tracing::{info, instrument, Level};
+
+use super::{Database, DbResult};
+use crate::utils::id::DbId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum AnomalyKind {
+    InvalidId,
+    OrphanedTag,
+    OrphanedImage,
+    FutureModifiedAt,
+    DuplicateName,
+}
+
+impl AnomalyKind {
+    /// Whether [`Database::repair_data`] takes care of this anomaly.
+    /// Duplicates have to be resolved by a human.
+    pub(crate) fn is_fixable(self) -> bool {
+        !matches!(self, AnomalyKind::DuplicateName)
+    }
+}
+
+impl Display for AnomalyKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            AnomalyKind::InvalidId => "invalid id",
+            AnomalyKind::OrphanedTag => "orphaned tag",
+            AnomalyKind::OrphanedImage => "orphaned image",
+            AnomalyKind::FutureModifiedAt => "modified in the future",
+            AnomalyKind::DuplicateName => "duplicate name",
+        };
+
+        f.write_str(kind)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Anomaly {
+    pub(crate) kind: AnomalyKind,
+    pub(crate) mare_id: String,
+    pub(crate) detail: String,
+}
+
+impl Database {
+    /// Scans the data for anomalies that the application doesn't expect.
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn check_data(&self) -> DbResult<Vec<Anomaly>> {
+        let mut anomalies = Vec::new();
+
+        let records = self.records().include_deleted().stored(&self.pool).await?;
+
+        // Checked by the same rule as `update_to_ulid` uses, so every reported id gets repaired
+        anomalies.extend(
+            records
+                .iter()
+                .filter(|record| !DbId::is_canonical(&record.id))
+                .map(|record| {
+                    let detail = if record.id.parse::<DbId>().is_ok() {
+                        format!("\"{}\" has an id in non-canonical form", record.name)
+                    } else {
+                        format!("\"{}\" has an id of unknown format", record.name)
+                    };
+
+                    Anomaly {
+                        kind: AnomalyKind::InvalidId,
+                        mare_id: record.id.clone(),
+                        detail,
+                    }
+                }),
+        );
+
+        // Deleted records keep their tags and images, so only missing ones count
+        let orphaned_tags = sqlx::query!(
+            r#"
+            select mare_id, tag
+            from mare_tags
+            where not exists (select from mares where mares.id = mare_tags.mare_id)
+            order by mare_id, tag
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        anomalies.extend(orphaned_tags.into_iter().map(|row| Anomaly {
+            kind: AnomalyKind::OrphanedTag,
+            mare_id: row.mare_id,
+            detail: format!("Tag \"{}\" belongs to a removed record", row.tag),
+        }));
+
+        let orphaned_images = sqlx::query!(
+            r#"
+            select mare_id, image_id
+            from mare_images
+            where not exists (select from mares where mares.id = mare_images.mare_id)
+            order by mare_id, image_id
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        anomalies.extend(orphaned_images.into_iter().map(|row| Anomaly {
+            kind: AnomalyKind::OrphanedImage,
+            mare_id: row.mare_id,
+            detail: format!("Image {} belongs to a removed record", row.image_id),
+        }));
+
+        let now = Utc::now();
+
+        anomalies.extend(
+            records
+                .iter()
+                .filter(|record| record.modified_at > now)
+                .map(|record| Anomaly {
+                    kind: AnomalyKind::FutureModifiedAt,
+                    mare_id: record.id.clone(),
+                    detail: format!("\"{}\" was modified at {}", record.name, record.modified_at),
+                }),
+        );
+
+        let duplicate_names = self.records().duplicate_names().await?;
+
+        anomalies.extend(duplicate_names.into_iter().map(|duplicate| Anomaly {
+            kind: AnomalyKind::DuplicateName,
+            mare_id: duplicate.id,
+            detail: format!(
+                "\"{}\" duplicates {}",
+                duplicate.name, duplicate.original_id
+            ),
+        }));
+
+        info!("Found {} anomalies", anomalies.len());
+
+        Ok(anomalies)
+    }
+
+    /// Fixes all anomalies that are [fixable](AnomalyKind::is_fixable).
+    /// Returns the number of fixed rows.
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn repair_data(&self) -> DbResult<u64> {
+        // Must come first, records with invalid ids can't be deserialized
+        let mut repaired = self.update_to_ulid().await?;
+
+        let mut transaction = self.pool.begin().await?;
+
+        repaired += sqlx::query!(
+            r#"
+            delete from mare_tags
+            where not exists (select from mares where mares.id = mare_tags.mare_id)
+            "#
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        repaired += sqlx::query!(
+            r#"
+            delete from mare_images
+            where not exists (select from mares where mares.id = mare_images.mare_id)
+            "#
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        repaired += sqlx::query!(
+            r#"
+            update mares
+            set modified_at = now(), edited_by = null, edit_nonce = null
+            where modified_at > now()
+            "#
+        )
+        .execute(&mut *transaction)
+        .await?
+        .rows_affected();
+
+        transaction.commit().await?;
+
+        info!("Repaired {repaired} rows");
+
+        Ok(repaired)
+    }
+}
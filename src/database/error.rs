@@ -0,0 +1,69 @@
+use std::fmt::Display;
+
+pub(crate) type DbResult<T> = std::result::Result<T, DbError>;
+
+#[derive(Debug)]
+pub(crate) enum DbError {
+    /// The record doesn't exist.
+    NotFound,
+    /// The record was changed by someone else, or a unique value is already taken.
+    Conflict(String),
+    /// The data violates a constraint of the schema.
+    Constraint(String),
+    /// The database cannot be reached.
+    Io(std::io::Error),
+    Other(anyhow::Error),
+}
+
+impl Display for DbError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbError::NotFound => f.write_str("Record not found"),
+            DbError::Conflict(message) => write!(f, "Conflict: {message}"),
+            DbError::Constraint(message) => write!(f, "Constraint violation: {message}"),
+            DbError::Io(err) => write!(f, "Cannot reach the database: {err}"),
+            DbError::Other(err) => write!(f, "Database error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbError::Io(err) => Some(err),
+            DbError::Other(err) => Some(err.as_ref()),
+            DbError::NotFound | DbError::Conflict(_) | DbError::Constraint(_) => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => DbError::NotFound,
+            sqlx::Error::Io(err) => DbError::Io(err),
+            sqlx::Error::PoolTimedOut => DbError::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "timed out while waiting for a connection",
+            )),
+            sqlx::Error::Database(db_err) => {
+                let message = db_err.message().to_owned();
+
+                match db_err.kind() {
+                    sqlx::error::ErrorKind::UniqueViolation => DbError::Conflict(message),
+                    sqlx::error::ErrorKind::ForeignKeyViolation
+                    | sqlx::error::ErrorKind::NotNullViolation
+                    | sqlx::error::ErrorKind::CheckViolation => DbError::Constraint(message),
+                    _ => DbError::Other(sqlx::Error::Database(db_err).into()),
+                }
+            }
+            err => DbError::Other(err.into()),
+        }
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for DbError {
+    fn from(err: sqlx::migrate::MigrateError) -> Self {
+        DbError::Other(err.into())
+    }
+}
@@ -0,0 +1,227 @@
+use std::{fmt::Display, str::FromStr, sync::Arc};
+
+use anyhow::anyhow;
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use tokio::sync::watch;
+
+use super::{Database, DatabaseRecord, DbError, DbResult};
+use crate::correlation;
+use crate::utils::id::DbId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MareEventKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl MareEventKind {
+    pub(crate) const ALL: [MareEventKind; 3] = [
+        MareEventKind::Created,
+        MareEventKind::Updated,
+        MareEventKind::Deleted,
+    ];
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            MareEventKind::Created => "created",
+            MareEventKind::Updated => "updated",
+            MareEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+impl Display for MareEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for MareEventKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str() == s)
+            .ok_or_else(|| anyhow!("Unknown event kind \"{s}\""))
+    }
+}
+
+/// Announces ids of committed events, so that interested tasks
+/// don't have to poll the database.
+#[derive(Debug, Clone)]
+pub(crate) struct EventBus(Arc<watch::Sender<i64>>);
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(0).0))
+    }
+}
+
+impl EventBus {
+    pub(crate) fn subscribe(&self) -> watch::Receiver<i64> {
+        self.0.subscribe()
+    }
+
+    /// Id of the latest event committed since the start, `0` if none.
+    pub(crate) fn latest(&self) -> i64 {
+        *self.0.borrow()
+    }
+
+    pub(super) fn publish(&self, event_id: i64) {
+        self.0.send_if_modified(|latest| {
+            let is_newer = event_id > *latest;
+            if is_newer {
+                *latest = event_id;
+            }
+            is_newer
+        });
+    }
+}
+
+/// Records a change of `record` and schedules its delivery to subscribed
+/// webhooks. Must be called in the transaction that made the change.
+pub(super) async fn record(
+    transaction: &mut Transaction<'_, Postgres>,
+    kind: MareEventKind,
+    record: &DatabaseRecord,
+) -> DbResult<i64> {
+    let payload = serde_json::to_value(record).map_err(|err| DbError::Other(err.into()))?;
+
+    let event_id = sqlx::query_scalar!(
+        r#"
+        insert into mare_events (mare_id, kind, payload, request_id)
+        values ($1, $2, $3, $4)
+        returning id
+        "#,
+        record.id as DbId,
+        kind.as_str(),
+        payload,
+        correlation::request_id()
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        insert into webhook_deliveries (event_id, webhook_id)
+        select $1, id
+        from webhooks
+        where $2 = any(events)
+        "#,
+        event_id,
+        kind.as_str()
+    )
+    .execute(&mut **transaction)
+    .await?;
+
+    Ok(event_id)
+}
+
+/// A committed change of a mare.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Change {
+    pub(crate) event_id: i64,
+    pub(crate) mare_id: DbId,
+    pub(crate) kind: MareEventKind,
+    pub(crate) created_at: DateTime<Utc>,
+}
+
+/// Number of changes of a mare during a day, in UTC.
+#[derive(Debug, Clone)]
+pub(crate) struct DailyActivity {
+    pub(crate) mare_id: DbId,
+    pub(crate) day: NaiveDate,
+    pub(crate) changes: i64,
+}
+
+impl Database {
+    /// Changes per mare per day, from the start of `since`. Days without changes are omitted.
+    pub(crate) async fn daily_activity(&self, since: NaiveDate) -> DbResult<Vec<DailyActivity>> {
+        let since = since.and_time(NaiveTime::MIN).and_utc();
+
+        let activity = sqlx::query_as!(
+            DailyActivity,
+            r#"
+            select
+                mare_id as "mare_id: DbId",
+                (created_at at time zone 'UTC')::date as "day!",
+                count(*) as "changes!"
+            from mare_events
+            where created_at >= $1
+            group by 1, 2
+            order by 1, 2
+            "#,
+            since
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(activity)
+    }
+
+    /// Id of the last recorded event, `0` if there are none.
+    pub(crate) async fn latest_event_id(&self) -> DbResult<i64> {
+        let id = sqlx::query_scalar!(r#"select coalesce(max(id), 0) as "id!" from mare_events"#)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Last event handled by `job`, `None` if it hasn't run yet.
+    pub(crate) async fn job_cursor(&self, job: &str) -> DbResult<Option<i64>> {
+        let event_id = sqlx::query_scalar!("select event_id from job_cursors where job = $1", job)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(event_id)
+    }
+
+    pub(crate) async fn set_job_cursor(&self, job: &str, event_id: i64) -> DbResult<()> {
+        sqlx::query!(
+            r#"
+            insert into job_cursors (job, event_id)
+            values ($1, $2)
+            on conflict (job) do update set event_id = excluded.event_id
+            "#,
+            job,
+            event_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Changes recorded after the event `after_id`, oldest first.
+    pub(crate) async fn changes_since(&self, after_id: i64, limit: i64) -> DbResult<Vec<Change>> {
+        sqlx::query!(
+            r#"
+            select id, mare_id as "mare_id: DbId", kind, created_at
+            from mare_events
+            where id > $1
+            order by id
+            limit $2
+            "#,
+            after_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok(Change {
+                event_id: row.id,
+                mare_id: row.mare_id,
+                kind: row.kind.parse().map_err(DbError::Other)?,
+                created_at: row.created_at,
+            })
+        })
+        .collect()
+    }
+}
@@ -0,0 +1,200 @@
+use chrono::{DateTime, Utc};
+use tracing::{info, instrument, Level};
+
+use super::{records::Records, Database, DbError, DbResult};
+use crate::utils::id::DbId;
+
+/// Image kept in the gallery of a mare. Its file is in [`ImageStorage`](crate::storage::ImageStorage).
+#[derive(Debug, Clone)]
+pub(crate) struct StoredImage {
+    pub(crate) mare_id: DbId,
+    pub(crate) mare_name: String,
+    pub(crate) image_id: i64,
+    pub(crate) url: String,
+    pub(crate) source_url: String,
+    /// When the image was kept, or kept again.
+    pub(crate) fetched_at: DateTime<Utc>,
+}
+
+/// Image kept by at least one gallery, with the url its file is downloaded from.
+#[derive(Debug, Clone)]
+pub(crate) struct KeptImage {
+    pub(crate) image_id: i64,
+    pub(crate) url: String,
+}
+
+impl Database {
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn record_image(
+        &self,
+        mare_id: DbId,
+        image_id: i64,
+        url: &str,
+        source_url: &str,
+    ) -> DbResult<()> {
+        sqlx::query!(
+            r#"
+            insert into mare_images (mare_id, image_id, url, source_url)
+            values ($1, $2, $3, $4)
+            on conflict (mare_id, image_id)
+            do update set
+                url = excluded.url,
+                source_url = excluded.source_url,
+                fetched_at = now()
+            "#,
+            mare_id as DbId,
+            image_id,
+            url,
+            source_url
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every kept image once, with the url it was last kept with.
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn kept_images(&self) -> DbResult<Vec<KeptImage>> {
+        let images = sqlx::query_as!(
+            KeptImage,
+            r#"
+            select distinct on (image_id) image_id, url
+            from mare_images
+            order by image_id, fetched_at desc
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(images)
+    }
+
+    /// Whether any gallery keeps the image, so its file is still needed.
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn is_image_kept(&self, image_id: i64) -> DbResult<bool> {
+        let is_kept = sqlx::query_scalar!(
+            r#"select exists (select from mare_images where image_id = $1) as "is_kept!""#,
+            image_id
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(is_kept)
+    }
+
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn count_images(&self) -> DbResult<i64> {
+        let count = sqlx::query_scalar!(r#"select count(*) as "count!" from mare_images"#)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    /// Forgets an image, so it is no longer exported or listed.
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn remove_image(&self, mare_id: DbId, image_id: i64) -> DbResult<()> {
+        let removed = sqlx::query!(
+            r#"
+            delete from mare_images
+            where mare_id = $1 and image_id = $2
+            "#,
+            mare_id as DbId,
+            image_id
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        if removed == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        info!("Removed image {image_id} of {mare_id}");
+
+        Ok(())
+    }
+}
+
+/// Images are read with the name of their record, so they follow its [`Scope`](super::records::Scope).
+impl Records<'_> {
+    /// Images kept in the gallery of a mare, newest first.
+    #[instrument(level = Level::DEBUG, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn images_of(&self, mare_id: DbId) -> DbResult<Vec<StoredImage>> {
+        let images = sqlx::query_as!(
+            StoredImage,
+            r#"
+            select
+                mare_images.mare_id as "mare_id: DbId", mares.name as mare_name,
+                mare_images.image_id, mare_images.url, mare_images.source_url,
+                mare_images.fetched_at
+            from mare_images
+            join mares on mares.id = mare_images.mare_id
+            where mare_images.mare_id = $1 and ($2 or mares.deleted_at is null)
+            order by mare_images.fetched_at desc, mare_images.image_id
+            "#,
+            mare_id as DbId,
+            self.scope.includes_deleted()
+        )
+        .fetch_all(&self.database.pool)
+        .await?;
+
+        Ok(images)
+    }
+
+    /// Images of all records, most recently kept first.
+    #[instrument(level = Level::DEBUG, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn list_images(&self, offset: i64, limit: i64) -> DbResult<Vec<StoredImage>> {
+        let images = sqlx::query_as!(
+            StoredImage,
+            r#"
+            select
+                mare_images.mare_id as "mare_id: DbId", mares.name as mare_name,
+                mare_images.image_id, mare_images.url, mare_images.source_url,
+                mare_images.fetched_at
+            from mare_images
+            join mares on mares.id = mare_images.mare_id
+            where $1 or mares.deleted_at is null
+            order by mare_images.fetched_at desc, mare_images.mare_id, mare_images.image_id
+            offset $2
+            limit $3
+            "#,
+            self.scope.includes_deleted(),
+            offset,
+            limit
+        )
+        .fetch_all(&self.database.pool)
+        .await?;
+
+        Ok(images)
+    }
+
+    #[instrument(level = Level::DEBUG, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn image(
+        &self,
+        mare_id: DbId,
+        image_id: i64,
+    ) -> DbResult<Option<StoredImage>> {
+        let image = sqlx::query_as!(
+            StoredImage,
+            r#"
+            select
+                mare_images.mare_id as "mare_id: DbId", mares.name as mare_name,
+                mare_images.image_id, mare_images.url, mare_images.source_url,
+                mare_images.fetched_at
+            from mare_images
+            join mares on mares.id = mare_images.mare_id
+            where mare_images.mare_id = $1 and mare_images.image_id = $2
+                and ($3 or mares.deleted_at is null)
+            "#,
+            mare_id as DbId,
+            image_id,
+            self.scope.includes_deleted()
+        )
+        .fetch_optional(&self.database.pool)
+        .await?;
+
+        Ok(image)
+    }
+}
@@ -1,16 +1,33 @@
-use anyhow::Result;
-use chrono::Utc;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
 use log::LevelFilter;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgConnectOptions, ConnectOptions, PgPool};
 use tracing::{info, instrument, warn, Level};
-use ulid::Ulid;
 use url::{self, Url};
 
 use crate::app::{AddPonyForm, EditPonyForm};
-use crate::utils::ulid::{DbUlid, DbUlidGen};
+use crate::config::DatabaseConfig;
+use crate::utils::id::{DbId, IdGenerator};
 
+mod archive;
+mod batch;
 pub(crate) mod breed;
+pub(crate) mod doctor;
+mod error;
+pub(crate) mod events;
+pub(crate) mod images;
+pub(crate) mod records;
+pub(crate) mod tags;
+pub(crate) mod webhooks;
+
+pub(crate) use batch::BatchOperation;
+
+pub(crate) use error::{DbError, DbResult};
+use events::{EventBus, MareEventKind};
 
 #[derive(Debug, Deserialize)]
 struct SetStatus {
@@ -19,7 +36,7 @@ struct SetStatus {
 
 #[repr(i32)]
 #[derive(Debug, Deserialize, sqlx::Type)]
-pub(crate) enum SetState {
+enum SetState {
     Success = 0,
     ModifiedAtConflict = 1,
     RecordNotFound = 2,
@@ -36,18 +53,27 @@ impl From<i32> for SetState {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct DatabaseRecord {
-    pub(crate) id: DbUlid,
+    pub(crate) id: DbId,
     pub(crate) name: String,
     pub(crate) breed: breed::Breed,
     pub(crate) modified_at: chrono::DateTime<Utc>,
 }
 
+/// Last successful save of the edit form of a record.
+#[derive(Debug, Clone)]
+pub(crate) struct LastEdit {
+    /// Client id of the browser that saved the form.
+    pub(crate) editor: String,
+    pub(crate) nonce: String,
+}
+
 #[derive(Clone)]
 pub(crate) struct Database {
     pool: PgPool,
-    ulid_gen: DbUlidGen,
+    id_gen: Arc<dyn IdGenerator>,
+    events: EventBus,
 }
 
 impl std::fmt::Debug for Database {
@@ -59,17 +85,48 @@ impl std::fmt::Debug for Database {
     }
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy)]
 pub(crate) enum PagingState {
     Next,
     Prev,
 }
 
+impl PagingState {
+    /// Path segments that can be parsed into a `PagingState`.
+    pub(crate) const VALUES: [&'static str; 2] = ["next", "prev"];
+}
+
+impl FromStr for PagingState {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "next" => Ok(PagingState::Next),
+            "prev" => Ok(PagingState::Prev),
+            _ => Err(anyhow!(
+                "Unknown paging state \"{s}\". Valid values: {}.",
+                Self::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
 impl Database {
+    /// Connects to the database and applies pending migrations.
+    pub(crate) async fn init(config: &DatabaseConfig) -> DbResult<Self> {
+        let database = Self::connect(config).await?;
+
+        database.migrate().await?;
+
+        Ok(database)
+    }
+
     #[instrument(level = Level::INFO)]
-    pub(crate) async fn init() -> Result<Self> {
-        let database_url = Url::parse(&std::env::var("DATABASE_URL")?)?;
+    pub(crate) async fn connect(config: &DatabaseConfig) -> DbResult<Self> {
+        let database_url = std::env::var("DATABASE_URL")
+            .map_err(|err| DbError::Other(anyhow!("Cannot read DATABASE_URL variable: {err}")))?;
+        let database_url = Url::parse(&database_url)
+            .map_err(|err| DbError::Other(anyhow!("Invalid DATABASE_URL: {err}")))?;
 
         let options = PgConnectOptions::from_url(&database_url)?
             .log_statements(LevelFilter::Debug)
@@ -79,34 +136,121 @@ impl Database {
 
         info!(
             database_url = database_url.to_string(),
+            id_format = ?config.id_format,
             "Established connection to database"
         );
 
-        sqlx::migrate!().run(&pool).await?;
-
         Ok(Self {
             pool,
-            ulid_gen: DbUlidGen::default(),
+            id_gen: config.id_format.generator(),
+            events: EventBus::default(),
         })
     }
 
+    pub(crate) fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn migrate(&self) -> DbResult<()> {
+        sqlx::migrate!().run(&self.pool).await?;
+
+        info!("Database migrations applied");
+
+        Ok(())
+    }
+
+    /// Replaces ids that are not [canonical](DbId::is_canonical). Valid ids are
+    /// rewritten in canonical form, and the others (e.g. left from the time `mares.id`
+    /// was a serial column) are replaced with ULIDs. The timestamp part of a new
+    /// ULID is taken from `modified_at`, so records keep their relative order.
+    /// Tags, images and the history follow their records. Returns the number of
+    /// updated records.
     #[instrument(level = Level::INFO, skip(self))]
-    pub(crate) async fn add(&self, data: &AddPonyForm) -> Result<Ulid> {
+    pub(crate) async fn update_to_ulid(&self) -> DbResult<u64> {
+        let mut transaction = self.pool.begin().await?;
+
+        let records = self
+            .records()
+            .include_deleted()
+            .stored_for_update(&mut transaction)
+            .await?;
+
+        let mut updated = 0;
+
+        for record in records {
+            if DbId::is_canonical(&record.id) {
+                continue;
+            }
+
+            let new_id = match record.id.parse::<DbId>() {
+                Ok(id) => id,
+                Err(_) => DbId::Ulid(ulid::Ulid::from_datetime(record.modified_at.into())),
+            };
+
+            // Tags and images follow through `on update cascade`
+
+            sqlx::query!(
+                r#"
+                update mares
+                set id = $1
+                where id = $2
+                "#,
+                new_id as DbId,
+                record.id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            // The history has no foreign key, as it outlives the records
+            sqlx::query!(
+                r#"
+                update mare_events
+                set mare_id = $1
+                where mare_id = $2
+                "#,
+                new_id as DbId,
+                record.id
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            info!(old_id = record.id, %new_id, "Replaced record id");
+
+            updated += 1;
+        }
+
+        transaction.commit().await?;
+
+        info!("Replaced {updated} record ids");
+
+        Ok(updated)
+    }
+
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn add(&self, data: &AddPonyForm) -> DbResult<DbId> {
         let breed: i32 = data.breed.into();
-        let id = self.ulid_gen.generate().to_string();
+        let id = self.id_gen.generate();
 
         let query = sqlx::query_as!(
             DatabaseRecord,
             r#"insert into mares (id, name, breed, modified_at)
             values ($1, $2, $3, CURRENT_TIMESTAMP)
-            returning id as "id!", name as "name!", breed as "breed!", modified_at as "modified_at!";
+            returning id as "id!: DbId", name as "name!", breed as "breed!", modified_at as "modified_at!";
             "#,
-            id,
+            id as DbId,
             data.name,
             breed
         );
 
-        let record = query.fetch_one(&self.pool).await?;
+        let mut transaction = self.pool.begin().await?;
+
+        let record = query.fetch_one(&mut *transaction).await?;
+
+        let event_id = events::record(&mut transaction, MareEventKind::Created, &record).await?;
+
+        transaction.commit().await?;
+        self.events.publish(event_id);
 
         // TODO rows_affected=1 rows_returned=0 elapsed=3.8952ms
         // structured logging
@@ -114,145 +258,149 @@ impl Database {
             name = record.name,
             breed = record.breed.to_string(),
             created_at = record.modified_at.to_string(),
-            id = record.id.0.to_string(),
+            id = record.id.to_string(),
             "Added new record: \"{}\", with id = {}",
             record.name,
-            record.id.0.to_string()
-        );
-
-        Ok(record.id.0)
-    }
-
-    #[instrument(level = Level::INFO, skip(self))]
-    pub(crate) async fn get(&self, id: &str) -> Result<Option<DatabaseRecord>> {
-        let query = sqlx::query_as!(
-            DatabaseRecord,
-            r#"
-            select id as "id!", name as "name!", breed as "breed!", modified_at as "modified_at!"
-            from mares
-            where id = $1
-            "#,
-            id
+            record.id.to_string()
         );
 
-        let record = query.fetch_optional(&self.pool).await?;
-
-        if let Some(record) = &record {
-            // TODO
-            info!(
-                name = record.name,
-                breed = record.breed.to_string(),
-                created_at = record.modified_at.to_string(),
-                id = record.id.0.to_string(),
-                "Received record: \"{}\", with id = {id}",
-                record.name
-            );
-        } else {
-            warn!("Record with id = {id} not found in database.");
-        }
-
-        Ok(record)
+        Ok(record.id)
     }
 
+    /// Fails with [`DbError::Conflict`] if the record was modified after
+    /// `data.modified_at`, and with [`DbError::NotFound`] if it doesn't exist.
+    /// `editor` is remembered with the nonce of the form, see [`Records::last_edit`](records::Records::last_edit).
     #[instrument(level = Level::INFO, skip(self))]
-    pub(crate) async fn set(&self, id: &str, data: &EditPonyForm) -> Result<SetState> {
+    pub(crate) async fn set(&self, id: DbId, data: &EditPonyForm, editor: &str) -> DbResult<()> {
         // TODO return previous record data
         // SQLite doesn't support this feature :/
         // https://stackoverflow.com/questions/6725964/sqlite-get-the-old-value-after-update
 
         let breed: i32 = data.breed.into();
 
+        let mut transaction = self.pool.begin().await?;
+
+        // `set_mare_record` doesn't know about deleted records
+        if self
+            .records()
+            .get_for_update(&mut transaction, id)
+            .await?
+            .is_none()
+        {
+            return Err(DbError::NotFound);
+        }
+
         let query = sqlx::query_as!(
             SetStatus,
             r#"
             select code as "code!"
             from set_mare_record($1, $2, $3, $4)
             "#,
-            id,
+            id as DbId,
             data.name,
             breed,
             data.modified_at
         );
 
-        let set_status = query.fetch_one(&self.pool).await?;
+        let set_status = query.fetch_one(&mut *transaction).await?;
 
-        Ok(set_status.code)
-    }
+        match set_status.code {
+            SetState::Success => {}
+            SetState::ModifiedAtConflict => {
+                return Err(DbError::Conflict(format!(
+                    "record with id = {id} has already changed"
+                )))
+            }
+            SetState::RecordNotFound => return Err(DbError::NotFound),
+        }
 
-    #[instrument(level = Level::INFO, skip(self))]
-    pub(crate) async fn list(&self) -> Result<Vec<DatabaseRecord>> {
-        let query = sqlx::query_as!(
-            DatabaseRecord,
+        sqlx::query!(
             r#"
-            select * from mares;
-            "#
-        );
+            update mares
+            set edited_by = $2, edit_nonce = $3
+            where id = $1
+            "#,
+            id as DbId,
+            editor,
+            data.nonce
+        )
+        .execute(&mut *transaction)
+        .await?;
 
-        let records = query.fetch_all(&self.pool).await?;
+        let record = self
+            .records()
+            .get_for_update(&mut transaction, id)
+            .await?
+            .ok_or(DbError::NotFound)?;
 
-        info!("List of records. Total records found: {}.", records.len());
+        let event_id = events::record(&mut transaction, MareEventKind::Updated, &record).await?;
 
-        Ok(records)
-    }
+        transaction.commit().await?;
+        self.events.publish(event_id);
 
-    #[instrument(level = Level::INFO, skip(self))]
-    pub(crate) async fn get_paged_records(
-        &self,
-        cursor: &str,
-        state: PagingState,
-    ) -> Result<Vec<DatabaseRecord>> {
-        let records = match state {
-            PagingState::Next => {
-                sqlx::query_as!(
-                    DatabaseRecord,
-                    r#"
-                select * from mares
-                where id > $1
-                order by id
-                asc limit 5
-                "#,
-                    cursor
-                )
-                .fetch_all(&self.pool)
-                .await?
-            }
-            PagingState::Prev => {
-                let mut records = sqlx::query_as!(
-                    DatabaseRecord,
-                    r#"
-                select * from mares
-                where id < $1
-                order by id
-                desc limit 5
-                "#,
-                    cursor
-                )
-                .fetch_all(&self.pool)
-                .await?;
-                records.reverse();
-                records
-            }
-        };
-
-        Ok(records)
+        Ok(())
     }
 
+    /// Removes records deleted before `deleted_before` for good, with their history.
+    /// Tags and images follow through `on delete cascade`. Returns the number of
+    /// removed records.
     #[instrument(level = Level::INFO, skip(self))]
-    pub(crate) async fn remove(&self, id: &str) -> Result<Option<DatabaseRecord>> {
-        let query = sqlx::query_as!(
-            DatabaseRecord,
+    pub(crate) async fn purge_deleted(&self, deleted_before: DateTime<Utc>) -> DbResult<u64> {
+        let mut transaction = self.pool.begin().await?;
+
+        let ids = sqlx::query_scalar!(
             r#"
             delete from mares
-            where id = $1
-            returning name as "name!", breed as "breed!", id as "id!", modified_at as "modified_at!"
+            where deleted_at < $1
+            returning id
             "#,
-            id
-        );
+            deleted_before
+        )
+        .fetch_all(&mut *transaction)
+        .await?;
+
+        // Pending webhook deliveries follow through `on delete cascade`
+        sqlx::query!(
+            r#"
+            delete from mare_events
+            where mare_id = any($1)
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        transaction.commit().await?;
+
+        info!("Purged {} deleted records", ids.len());
+
+        Ok(ids.len() as u64)
+    }
+
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn remove(&self, id: DbId) -> DbResult<Option<DatabaseRecord>> {
+        let mut transaction = self.pool.begin().await?;
+
+        let record = self.records().get_for_update(&mut transaction, id).await?;
+
+        if let Some(record) = &record {
+            sqlx::query!(
+                r#"
+                update mares
+                set deleted_at = now(), edited_by = null, edit_nonce = null
+                where id = $1
+                "#,
+                id as DbId
+            )
+            .execute(&mut *transaction)
+            .await?;
+
+            let event_id = events::record(&mut transaction, MareEventKind::Deleted, record).await?;
 
-        let record = query.fetch_optional(&self.pool).await?;
+            transaction.commit().await?;
+            self.events.publish(event_id);
 
-        if record.is_some() {
-            info!("Record with id = {id} removed from database.");
+            info!("Record with id = {id} marked as deleted.");
         } else {
             warn!("Record with id = {id} not found in database.",);
         }
@@ -0,0 +1,446 @@
+//! Reads of the `mares` table.
+//!
+//! Deleted records stay in the table with `deleted_at` set. To keep them from
+//! leaking into pages, every query that reads `mares` binds a [`Scope`], and
+//! the rest of the application reads records only through [`Records`].
+
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use tracing::{info, instrument, warn, Level};
+
+use super::{breed::Breed, Database, DatabaseRecord, DbResult, LastEdit, PagingState};
+use crate::utils::id::DbId;
+
+/// Which records a query sees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum Scope {
+    #[default]
+    Live,
+    IncludeDeleted,
+}
+
+impl Scope {
+    /// Value for the `($n or deleted_at is null)` predicate.
+    pub(super) fn includes_deleted(self) -> bool {
+        self == Scope::IncludeDeleted
+    }
+}
+
+/// Order of records in the mare table. Records are ordered by `created_at`
+/// rather than by id, as ids of some formats don't follow the creation time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum SortOrder {
+    #[default]
+    Newest,
+    Name,
+}
+
+impl SortOrder {
+    pub(crate) const VALUES: [&'static str; 2] = ["newest", "name"];
+}
+
+impl FromStr for SortOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "newest" => Ok(SortOrder::Newest),
+            "name" => Ok(SortOrder::Name),
+            _ => Err(anyhow!(
+                "Unknown sort order \"{s}\", expected one of: {}",
+                Self::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Record as it is stored, with an id that doesn't have to be a valid [`DbId`].
+/// Read by repairs and backups, which have to see every record.
+#[derive(Debug, Clone)]
+pub(crate) struct StoredRecord {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) breed: Breed,
+    pub(crate) created_at: DateTime<Utc>,
+    pub(crate) modified_at: DateTime<Utc>,
+    pub(crate) deleted_at: Option<DateTime<Utc>>,
+}
+
+/// Record whose name differs from the name of an older record
+/// only in case and whitespace.
+#[derive(Debug, Clone)]
+pub(crate) struct DuplicateName {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) original_id: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Records<'a> {
+    pub(super) database: &'a Database,
+    pub(super) scope: Scope,
+}
+
+impl Database {
+    /// Reads records, skipping the deleted ones
+    /// unless [`Records::include_deleted`] is called.
+    pub(crate) fn records(&self) -> Records<'_> {
+        Records {
+            database: self,
+            scope: Scope::Live,
+        }
+    }
+}
+
+impl Records<'_> {
+    pub(crate) fn include_deleted(self) -> Self {
+        Self {
+            scope: Scope::IncludeDeleted,
+            ..self
+        }
+    }
+
+    /// Reads the record and locks it until `transaction` ends.
+    #[instrument(level = Level::DEBUG, skip(self, transaction), fields(scope = ?self.scope))]
+    pub(super) async fn get_for_update(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        id: DbId,
+    ) -> DbResult<Option<DatabaseRecord>> {
+        let record = sqlx::query_as!(
+            DatabaseRecord,
+            r#"
+            select id as "id: DbId", name, breed, modified_at
+            from mares
+            where id = $1 and ($2 or deleted_at is null)
+            for update
+            "#,
+            id as DbId,
+            self.scope.includes_deleted()
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Reads the records with the given ids and locks them until `transaction` ends.
+    /// Ids that don't exist are skipped.
+    #[instrument(level = Level::DEBUG, skip(self, transaction), fields(scope = ?self.scope))]
+    pub(super) async fn get_many_for_update(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+        ids: &[DbId],
+    ) -> DbResult<Vec<DatabaseRecord>> {
+        let records = sqlx::query_as!(
+            DatabaseRecord,
+            r#"
+            select id as "id: DbId", name, breed, modified_at
+            from mares
+            where id = any($1) and ($2 or deleted_at is null)
+            order by id
+            for update
+            "#,
+            ids as &[DbId],
+            self.scope.includes_deleted()
+        )
+        .fetch_all(&mut **transaction)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Records ordered by id, as they are stored.
+    #[instrument(level = Level::DEBUG, skip(self, executor), fields(scope = ?self.scope))]
+    pub(super) async fn stored(
+        &self,
+        executor: impl PgExecutor<'_>,
+    ) -> DbResult<Vec<StoredRecord>> {
+        let records = sqlx::query_as!(
+            StoredRecord,
+            r#"
+            select id, name, breed, created_at, modified_at, deleted_at
+            from mares
+            where $1 or deleted_at is null
+            order by id
+            "#,
+            self.scope.includes_deleted()
+        )
+        .fetch_all(executor)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Same as [`Records::stored`], but locks the records until `transaction` ends.
+    #[instrument(level = Level::DEBUG, skip(self, transaction), fields(scope = ?self.scope))]
+    pub(super) async fn stored_for_update(
+        &self,
+        transaction: &mut Transaction<'_, Postgres>,
+    ) -> DbResult<Vec<StoredRecord>> {
+        let records = sqlx::query_as!(
+            StoredRecord,
+            r#"
+            select id, name, breed, created_at, modified_at, deleted_at
+            from mares
+            where $1 or deleted_at is null
+            order by id
+            for update
+            "#,
+            self.scope.includes_deleted()
+        )
+        .fetch_all(&mut **transaction)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Records whose names match older records once case and whitespace are ignored.
+    #[instrument(level = Level::DEBUG, skip(self), fields(scope = ?self.scope))]
+    pub(super) async fn duplicate_names(&self) -> DbResult<Vec<DuplicateName>> {
+        let duplicates = sqlx::query_as!(
+            DuplicateName,
+            r#"
+            select id as "id!", name as "name!", original_id as "original_id!"
+            from (
+                select
+                    id, name,
+                    first_value(id) over (
+                        partition by lower(regexp_replace(trim(name), '\s+', ' ', 'g'))
+                        order by modified_at, id
+                    ) as original_id
+                from mares
+                where $1 or deleted_at is null
+            ) as normalized
+            where id <> original_id
+            order by id
+            "#,
+            self.scope.includes_deleted()
+        )
+        .fetch_all(&self.database.pool)
+        .await?;
+
+        Ok(duplicates)
+    }
+
+    #[instrument(level = Level::INFO, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn get(&self, id: DbId) -> DbResult<Option<DatabaseRecord>> {
+        let query = sqlx::query_as!(
+            DatabaseRecord,
+            r#"
+            select id as "id: DbId", name, breed, modified_at
+            from mares
+            where id = $1 and ($2 or deleted_at is null)
+            "#,
+            id as DbId,
+            self.scope.includes_deleted()
+        );
+
+        let record = query.fetch_optional(&self.database.pool).await?;
+
+        if let Some(record) = &record {
+            // TODO
+            info!(
+                name = record.name,
+                breed = record.breed.to_string(),
+                created_at = record.modified_at.to_string(),
+                id = record.id.to_string(),
+                "Received record: \"{}\", with id = {id}",
+                record.name
+            );
+        } else {
+            warn!("Record with id = {id} not found in database.");
+        }
+
+        Ok(record)
+    }
+
+    #[instrument(level = Level::INFO, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn list(&self, order: SortOrder) -> DbResult<Vec<DatabaseRecord>> {
+        let query = sqlx::query_as!(
+            DatabaseRecord,
+            r#"
+            select id as "id: DbId", name, breed, modified_at
+            from mares
+            where $1 or deleted_at is null
+            order by case when $2 then name end, created_at desc, id desc
+            "#,
+            self.scope.includes_deleted(),
+            order == SortOrder::Name
+        );
+
+        let records = query.fetch_all(&self.database.pool).await?;
+
+        info!("List of records. Total records found: {}.", records.len());
+
+        Ok(records)
+    }
+
+    /// Number of records of every breed. Breeds without records are omitted.
+    #[instrument(level = Level::INFO, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn count_by_breed(&self) -> DbResult<Vec<(Breed, i64)>> {
+        let counts = sqlx::query!(
+            r#"
+            select breed, count(*) as "count!"
+            from mares
+            where $1 or deleted_at is null
+            group by breed
+            order by breed
+            "#,
+            self.scope.includes_deleted()
+        )
+        .fetch_all(&self.database.pool)
+        .await?;
+
+        Ok(counts
+            .into_iter()
+            .map(|row| (Breed::from(row.breed), row.count))
+            .collect())
+    }
+
+    /// Page of records after or before the `cursor` record in `order`. Without
+    /// a cursor, pages start from the first record, or from the last one going back.
+    #[instrument(level = Level::INFO, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn paged(
+        &self,
+        order: SortOrder,
+        cursor: Option<DbId>,
+        state: PagingState,
+    ) -> DbResult<Vec<DatabaseRecord>> {
+        // Same order as `list`: by name with the newest first among namesakes, or newest first
+        let records = match state {
+            PagingState::Next => {
+                sqlx::query_as!(
+                    DatabaseRecord,
+                    r#"
+                select id as "id: DbId", name, breed, modified_at from mares
+                where ($2 or deleted_at is null) and (
+                    $1::varchar is null
+                    or exists (
+                        select from mares as cursor
+                        where cursor.id = $1 and case
+                            when $3 then mares.name > cursor.name
+                                or (mares.name = cursor.name
+                                    and (mares.created_at, mares.id) < (cursor.created_at, cursor.id))
+                            else (mares.created_at, mares.id) < (cursor.created_at, cursor.id)
+                        end
+                    )
+                )
+                order by case when $3 then name end, created_at desc, id desc
+                limit 5
+                "#,
+                    cursor as Option<DbId>,
+                    self.scope.includes_deleted(),
+                    order == SortOrder::Name
+                )
+                .fetch_all(&self.database.pool)
+                .await?
+            }
+            PagingState::Prev => {
+                let mut records = sqlx::query_as!(
+                    DatabaseRecord,
+                    r#"
+                select id as "id: DbId", name, breed, modified_at from mares
+                where ($2 or deleted_at is null) and (
+                    $1::varchar is null
+                    or exists (
+                        select from mares as cursor
+                        where cursor.id = $1 and case
+                            when $3 then mares.name < cursor.name
+                                or (mares.name = cursor.name
+                                    and (mares.created_at, mares.id) > (cursor.created_at, cursor.id))
+                            else (mares.created_at, mares.id) > (cursor.created_at, cursor.id)
+                        end
+                    )
+                )
+                order by case when $3 then name end desc, created_at, id
+                limit 5
+                "#,
+                    cursor as Option<DbId>,
+                    self.scope.includes_deleted(),
+                    order == SortOrder::Name
+                )
+                .fetch_all(&self.database.pool)
+                .await?;
+                records.reverse();
+                records
+            }
+        };
+
+        Ok(records)
+    }
+
+    /// Records in the order they were created, for listings that don't fit on
+    /// a single page. New records are added to the last slice.
+    #[instrument(level = Level::INFO, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn slice(&self, offset: i64, limit: i64) -> DbResult<Vec<DatabaseRecord>> {
+        let records = sqlx::query_as!(
+            DatabaseRecord,
+            r#"
+            select id as "id: DbId", name, breed, modified_at
+            from mares
+            where $1 or deleted_at is null
+            order by created_at, id
+            offset $2
+            limit $3
+            "#,
+            self.scope.includes_deleted(),
+            offset,
+            limit
+        )
+        .fetch_all(&self.database.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Latest `modified_at` of every slice of `page_size` records, see [`Records::slice`].
+    #[instrument(level = Level::INFO, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn last_modified_by_page(
+        &self,
+        page_size: i64,
+    ) -> DbResult<Vec<DateTime<Utc>>> {
+        let pages = sqlx::query_scalar!(
+            r#"
+            select max(modified_at) as "last_modified!"
+            from (
+                select modified_at, (row_number() over (order by created_at, id) - 1) / $2 as page
+                from mares
+                where $1 or deleted_at is null
+            ) as numbered
+            group by page
+            order by page
+            "#,
+            self.scope.includes_deleted(),
+            page_size
+        )
+        .fetch_all(&self.database.pool)
+        .await?;
+
+        Ok(pages)
+    }
+
+    /// Who saved the record last and from which form, if it was saved
+    /// since this was tracked.
+    #[instrument(level = Level::DEBUG, skip(self), fields(scope = ?self.scope))]
+    pub(crate) async fn last_edit(&self, id: DbId) -> DbResult<Option<LastEdit>> {
+        let edit = sqlx::query_as!(
+            LastEdit,
+            r#"
+            select edited_by as "editor!", edit_nonce as "nonce!"
+            from mares
+            where id = $1 and ($2 or deleted_at is null)
+                and edited_by is not null and edit_nonce is not null
+            "#,
+            id as DbId,
+            self.scope.includes_deleted()
+        )
+        .fetch_optional(&self.database.pool)
+        .await?;
+
+        Ok(edit)
+    }
+}
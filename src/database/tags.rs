@@ -0,0 +1,36 @@
+use std::collections::HashMap;
+
+use tracing::{instrument, Level};
+
+use super::{Database, DbResult};
+use crate::utils::id::DbId;
+
+/// Maximum length of a tag, as defined in the `mare_tags` table.
+pub(crate) const MAX_TAG_LENGTH: usize = 50;
+
+impl Database {
+    /// Tags of the given records, sorted alphabetically.
+    /// Records without tags are omitted.
+    #[instrument(level = Level::INFO, skip(self, ids))]
+    pub(crate) async fn tags_by_mare(&self, ids: &[DbId]) -> DbResult<HashMap<DbId, Vec<String>>> {
+        let rows = sqlx::query!(
+            r#"
+            select mare_id as "mare_id: DbId", tag
+            from mare_tags
+            where mare_id = any($1)
+            order by tag
+            "#,
+            ids as &[DbId]
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags: HashMap<DbId, Vec<String>> = HashMap::new();
+
+        for row in rows {
+            tags.entry(row.mare_id).or_default().push(row.tag);
+        }
+
+        Ok(tags)
+    }
+}
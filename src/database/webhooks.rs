@@ -0,0 +1,184 @@
+use chrono::{DateTime, Utc};
+use tracing::{info, instrument, warn, Level};
+
+use super::{events::MareEventKind, Database, DbError, DbResult};
+use crate::utils::id::DbId;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Webhook {
+    pub(crate) id: DbId,
+    pub(crate) url: String,
+    pub(crate) events: Vec<String>,
+    pub(crate) secret: String,
+    pub(crate) created_at: DateTime<Utc>,
+    /// Deliveries that are not delivered yet, but will be retried.
+    pub(crate) pending: i64,
+    /// Deliveries that ran out of attempts.
+    pub(crate) failed: i64,
+}
+
+#[derive(Debug)]
+pub(crate) struct NewWebhook {
+    pub(crate) url: String,
+    pub(crate) events: Vec<MareEventKind>,
+    pub(crate) secret: String,
+}
+
+#[derive(Debug)]
+pub(crate) struct PendingDelivery {
+    pub(crate) event_id: i64,
+    pub(crate) webhook_id: DbId,
+    pub(crate) url: String,
+    pub(crate) secret: String,
+    pub(crate) attempts: i32,
+    pub(crate) kind: String,
+    pub(crate) payload: serde_json::Value,
+    pub(crate) request_id: Option<String>,
+    pub(crate) occurred_at: DateTime<Utc>,
+}
+
+impl Database {
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn list_webhooks(&self, max_attempts: i32) -> DbResult<Vec<Webhook>> {
+        let webhooks = sqlx::query_as!(
+            Webhook,
+            r#"
+            select
+                webhooks.id as "id: DbId", webhooks.url, webhooks.events, webhooks.secret, webhooks.created_at,
+                count(deliveries.event_id) filter (where deliveries.attempts < $1) as "pending!",
+                count(deliveries.event_id) filter (where deliveries.attempts >= $1) as "failed!"
+            from webhooks
+            left join webhook_deliveries as deliveries
+                on deliveries.webhook_id = webhooks.id and deliveries.delivered_at is null
+            group by webhooks.id
+            order by webhooks.created_at
+            "#,
+            max_attempts
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(webhooks)
+    }
+
+    #[instrument(level = Level::INFO, skip(self, webhook), fields(url = webhook.url))]
+    pub(crate) async fn add_webhook(&self, webhook: &NewWebhook) -> DbResult<DbId> {
+        let id = self.id_gen.generate();
+        let events: Vec<String> = webhook
+            .events
+            .iter()
+            .map(|kind| kind.as_str().to_owned())
+            .collect();
+
+        sqlx::query!(
+            r#"
+            insert into webhooks (id, url, events, secret)
+            values ($1, $2, $3, $4)
+            "#,
+            id as DbId,
+            webhook.url,
+            &events,
+            webhook.secret
+        )
+        .execute(&self.pool)
+        .await?;
+
+        info!(%id, "Registered webhook for {}", events.join(", "));
+
+        Ok(id)
+    }
+
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn remove_webhook(&self, id: DbId) -> DbResult<()> {
+        let result = sqlx::query!("delete from webhooks where id = $1", id as DbId)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            warn!("Webhook with id = {id} not found in database.");
+            return Err(DbError::NotFound);
+        }
+
+        info!("Webhook with id = {id} removed from database.");
+
+        Ok(())
+    }
+
+    /// Deliveries that are due for an attempt, oldest events first.
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn due_deliveries(
+        &self,
+        max_attempts: i32,
+        limit: i64,
+    ) -> DbResult<Vec<PendingDelivery>> {
+        let deliveries = sqlx::query_as!(
+            PendingDelivery,
+            r#"
+            select
+                deliveries.event_id, deliveries.webhook_id as "webhook_id: DbId", deliveries.attempts,
+                webhooks.url, webhooks.secret,
+                events.kind, events.payload, events.request_id,
+                events.created_at as occurred_at
+            from webhook_deliveries as deliveries
+            join webhooks on webhooks.id = deliveries.webhook_id
+            join mare_events as events on events.id = deliveries.event_id
+            where deliveries.delivered_at is null
+                and deliveries.attempts < $1
+                and deliveries.next_attempt_at <= now()
+            order by deliveries.event_id
+            limit $2
+            "#,
+            max_attempts,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(deliveries)
+    }
+
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn mark_delivered(&self, event_id: i64, webhook_id: DbId) -> DbResult<()> {
+        sqlx::query!(
+            r#"
+            update webhook_deliveries
+            set attempts = attempts + 1, delivered_at = now(), last_error = null
+            where event_id = $1 and webhook_id = $2
+            "#,
+            event_id,
+            webhook_id as DbId
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn mark_failed(
+        &self,
+        event_id: i64,
+        webhook_id: DbId,
+        error: &str,
+        retry_in: std::time::Duration,
+    ) -> DbResult<()> {
+        let retry_in =
+            chrono::Duration::from_std(retry_in).map_err(|err| DbError::Other(err.into()))?;
+
+        sqlx::query!(
+            r#"
+            update webhook_deliveries
+            set attempts = attempts + 1, next_attempt_at = $3, last_error = $4
+            where event_id = $1 and webhook_id = $2
+            "#,
+            event_id,
+            webhook_id as DbId,
+            Utc::now() + retry_in,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
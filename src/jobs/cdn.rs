@@ -0,0 +1,76 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use serde_json::json;
+
+use crate::config::{CdnConfig, CdnProvider};
+use crate::database::Database;
+use crate::utils::id::DbId;
+
+/// Key of every page that lists records.
+pub(crate) const COLLECTION_KEY: &str = "mares";
+
+/// Name of the job in `job_cursors`.
+const CURSOR: &str = "cdn_purge";
+
+/// Number of changes purged in a single run.
+const BATCH_SIZE: i64 = 500;
+
+/// Key of the pages of a single record.
+pub(crate) fn record_key(id: DbId) -> String {
+    format!("mare-{id}")
+}
+
+/// Purges pages of the records changed since the last run from the CDN.
+///
+/// The id of the last purged event is kept in the database, so changes made
+/// while the server was down or by the CLI are purged on the next run. The
+/// first run only purges the listings and starts from the latest event.
+pub(crate) async fn purge(
+    database: Database,
+    client: reqwest::Client,
+    config: CdnConfig,
+) -> Result<String> {
+    let Some(purge_url) = &config.purge_url else {
+        return Ok("Disabled".to_owned());
+    };
+
+    let mut keys = BTreeSet::from([COLLECTION_KEY.to_owned()]);
+
+    let (last_event_id, changes) = match database.job_cursor(CURSOR).await? {
+        Some(last_purged) => {
+            let changes = database.changes_since(last_purged, BATCH_SIZE).await?;
+            let Some(last) = changes.last() else {
+                return Ok("Nothing to purge".to_owned());
+            };
+
+            keys.extend(changes.iter().map(|change| record_key(change.mare_id)));
+
+            (last.event_id, changes.len())
+        }
+        None => (database.latest_event_id().await?, 0),
+    };
+
+    let body = match config.provider {
+        CdnProvider::Fastly => json!({ "surrogate_keys": keys }),
+        CdnProvider::Cloudflare => json!({ "tags": keys }),
+    };
+
+    let mut request = client
+        .post(purge_url.clone())
+        .timeout(config.timeout)
+        .json(&body);
+
+    if let Some(token) = &config.purge_token {
+        request = match config.provider {
+            CdnProvider::Fastly => request.header("fastly-key", token),
+            CdnProvider::Cloudflare => request.bearer_auth(token),
+        };
+    }
+
+    request.send().await?.error_for_status()?;
+
+    database.set_job_cursor(CURSOR, last_event_id).await?;
+
+    Ok(format!("Purged {} keys of {changes} changes", keys.len()))
+}
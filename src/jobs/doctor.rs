@@ -0,0 +1,36 @@
+use anyhow::Result;
+use itertools::Itertools;
+
+use crate::database::doctor::Anomaly;
+use crate::database::Database;
+
+/// Scans the data for anomalies, repairing the fixable ones if `repair` is set.
+pub(crate) async fn check(database: Database, repair: bool) -> Result<String> {
+    let anomalies = database.check_data().await?;
+
+    if !repair || !anomalies.iter().any(|anomaly| anomaly.kind.is_fixable()) {
+        return Ok(summary(&anomalies));
+    }
+
+    let repaired = database.repair_data().await?;
+    let remaining = database.check_data().await?;
+
+    Ok(format!("{repaired} rows repaired, {}", summary(&remaining)))
+}
+
+/// E.g. `3 anomalies (2 orphaned tag, 1 duplicate name)`.
+pub(crate) fn summary(anomalies: &[Anomaly]) -> String {
+    if anomalies.is_empty() {
+        return "no anomalies".to_owned();
+    }
+
+    let by_kind = anomalies
+        .iter()
+        .counts_by(|anomaly| anomaly.kind)
+        .into_iter()
+        .sorted()
+        .map(|(kind, count)| format!("{count} {kind}"))
+        .join(", ");
+
+    format!("{} anomalies ({by_kind})", anomalies.len())
+}
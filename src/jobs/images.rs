@@ -0,0 +1,65 @@
+use std::{collections::HashSet, time::Duration};
+
+use anyhow::Result;
+use chrono::Utc;
+use tracing::warn;
+
+use crate::app::derpibooru::Derpibooru;
+use crate::database::Database;
+use crate::storage::ImageStorage;
+
+/// Downloads kept images whose files are missing or older than `max_age`,
+/// and removes the files of images that no gallery keeps anymore.
+pub(crate) async fn refresh(
+    database: Database,
+    derpibooru: Derpibooru,
+    storage: ImageStorage,
+    max_age: Duration,
+) -> Result<String> {
+    let stale_before = Utc::now() - chrono::Duration::from_std(max_age)?;
+
+    // Listed before the kept images are read. Keeping an image records it
+    // before storing the file, so a listed file of a kept image is never removed
+    let stored = storage.list().await?;
+    let kept = database.kept_images().await?;
+
+    let mut refreshed = 0;
+    let mut failed = 0;
+
+    for image in &kept {
+        let file = storage.metadata(image.image_id).await?;
+
+        if file
+            .and_then(|file| file.modified_at)
+            .is_some_and(|modified_at| modified_at > stale_before)
+        {
+            continue;
+        }
+
+        match derpibooru.download(&image.url).await {
+            Ok(bytes) => {
+                storage.save(image.image_id, &bytes).await?;
+                refreshed += 1;
+            }
+            Err(err) => {
+                warn!(image_id = image.image_id, "Cannot download image: {err}");
+                failed += 1;
+            }
+        }
+    }
+
+    let kept: HashSet<i64> = kept.iter().map(|image| image.image_id).collect();
+
+    let mut removed = 0;
+
+    for image_id in stored {
+        if !kept.contains(&image_id) {
+            storage.remove(image_id).await?;
+            removed += 1;
+        }
+    }
+
+    Ok(format!(
+        "{refreshed} images refreshed, {failed} failed, {removed} unused files removed"
+    ))
+}
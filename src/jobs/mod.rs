@@ -0,0 +1,187 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tokio::{sync::watch, task::JoinHandle, time::MissedTickBehavior};
+use tracing::{error, info, info_span, Instrument};
+
+pub(crate) mod cdn;
+pub(crate) mod doctor;
+pub(crate) mod images;
+pub(crate) mod purge;
+pub(crate) mod webhooks;
+
+#[derive(Debug, Clone)]
+pub(crate) struct JobStatus {
+    pub(crate) name: &'static str,
+    pub(crate) interval: Duration,
+    pub(crate) runs: u64,
+    pub(crate) last_run: Option<JobRun>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct JobRun {
+    pub(crate) started_at: DateTime<Utc>,
+    pub(crate) duration: Duration,
+    /// Summary of the work done, or the error message.
+    pub(crate) outcome: Result<String, String>,
+}
+
+/// Last known state of every registered job, shared with the admin page.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct JobStatuses(Arc<Mutex<BTreeMap<&'static str, JobStatus>>>);
+
+impl JobStatuses {
+    pub(crate) fn list(&self) -> Vec<JobStatus> {
+        self.0.lock().unwrap().values().cloned().collect()
+    }
+
+    fn update(&self, name: &'static str, update: impl FnOnce(&mut JobStatus)) {
+        if let Some(status) = self.0.lock().unwrap().get_mut(name) {
+            update(status);
+        }
+    }
+}
+
+/// Runs registered jobs periodically until [`JobRunner::shutdown`] is called.
+pub(crate) struct JobRunner {
+    statuses: JobStatuses,
+    shutdown: watch::Sender<bool>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl JobRunner {
+    pub(crate) fn new() -> Self {
+        let (shutdown, _) = watch::channel(false);
+
+        Self {
+            statuses: JobStatuses::default(),
+            shutdown,
+            tasks: Vec::new(),
+        }
+    }
+
+    pub(crate) fn statuses(&self) -> JobStatuses {
+        self.statuses.clone()
+    }
+
+    /// Spawns a task that calls `job` every `interval`, starting immediately.
+    /// A zero `interval` disables the job. The job returns a short summary of
+    /// the work done, which is logged and shown on the admin page.
+    pub(crate) fn register<F, Fut>(&mut self, name: &'static str, interval: Duration, job: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send,
+    {
+        self.spawn(name, interval, None, job);
+    }
+
+    /// Like [`JobRunner::register`], but also runs the job whenever `wakeup` changes.
+    pub(crate) fn register_with_wakeup<F, Fut>(
+        &mut self,
+        name: &'static str,
+        interval: Duration,
+        wakeup: watch::Receiver<i64>,
+        job: F,
+    ) where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send,
+    {
+        self.spawn(name, interval, Some(wakeup), job);
+    }
+
+    fn spawn<F, Fut>(
+        &mut self,
+        name: &'static str,
+        interval: Duration,
+        mut wakeup: Option<watch::Receiver<i64>>,
+        job: F,
+    ) where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send,
+    {
+        if interval.is_zero() {
+            info!(job = name, "Job is disabled");
+            return;
+        }
+
+        self.statuses.0.lock().unwrap().insert(
+            name,
+            JobStatus {
+                name,
+                interval,
+                runs: 0,
+                last_run: None,
+            },
+        );
+
+        let statuses = self.statuses.clone();
+        let mut shutdown = self.shutdown.subscribe();
+
+        let task = async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {},
+                    Ok(()) = changed(&mut wakeup) => {},
+                    _ = shutdown.changed() => break,
+                }
+
+                let started_at = Utc::now();
+                let timer = Instant::now();
+
+                let outcome = job()
+                    .instrument(info_span!("job", job = name))
+                    .await
+                    .map_err(|err| format!("{err:#}"));
+
+                let duration = timer.elapsed();
+
+                match &outcome {
+                    Ok(summary) => info!(job = name, ?duration, "Job finished: {summary}"),
+                    Err(err) => error!(job = name, ?duration, "Job failed: {err}"),
+                }
+
+                statuses.update(name, |status| {
+                    status.runs += 1;
+                    status.last_run = Some(JobRun {
+                        started_at,
+                        duration,
+                        outcome,
+                    });
+                });
+            }
+
+            info!(job = name, "Job stopped");
+        };
+
+        self.tasks.push(tokio::spawn(task));
+    }
+
+    /// Stops scheduling new runs and waits for the running ones to finish.
+    pub(crate) async fn shutdown(self) {
+        info!("Shutting down background jobs");
+
+        let _ = self.shutdown.send(true);
+
+        for task in self.tasks {
+            if let Err(err) = task.await {
+                error!("Background job panicked: {err}");
+            }
+        }
+    }
+}
+
+async fn changed(wakeup: &mut Option<watch::Receiver<i64>>) -> Result<(), watch::error::RecvError> {
+    match wakeup {
+        Some(wakeup) => wakeup.changed().await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+
+use crate::database::Database;
+
+/// Removes records that were deleted more than `retention` ago for good.
+pub(crate) async fn purge(database: Database, retention: Duration) -> Result<String> {
+    let deleted_before = Utc::now() - chrono::Duration::from_std(retention)?;
+
+    let purged = database.purge_deleted(deleted_before).await?;
+
+    Ok(format!("{purged} deleted records removed"))
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::header;
+use serde::Serialize;
+use sha2::Sha256;
+use tracing::{info_span, warn, Instrument};
+
+use crate::config::WebhooksConfig;
+use crate::correlation;
+use crate::database::webhooks::PendingDelivery;
+use crate::database::Database;
+
+pub(crate) const EVENT_HEADER: &str = "x-mare-event";
+pub(crate) const SIGNATURE_HEADER: &str = "x-mare-signature-256";
+
+/// Deliveries are retried with exponential backoff starting from this delay.
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Number of deliveries attempted in a single run.
+const BATCH_SIZE: i64 = 100;
+
+#[derive(Serialize)]
+struct WebhookBody<'a> {
+    /// Human readable summary, shown by Discord and similar chat services.
+    content: String,
+    event_id: i64,
+    event: &'a str,
+    occurred_at: DateTime<Utc>,
+    mare: &'a serde_json::Value,
+}
+
+/// Delivers pending webhook events, rescheduling the failed ones.
+pub(crate) async fn deliver(
+    database: Database,
+    client: reqwest::Client,
+    config: WebhooksConfig,
+) -> Result<String> {
+    let deliveries = database
+        .due_deliveries(config.max_attempts, BATCH_SIZE)
+        .await?;
+
+    let mut delivered = 0;
+    let mut failed = 0;
+
+    for delivery in deliveries {
+        let span = info_span!(
+            "webhook_delivery",
+            event_id = delivery.event_id,
+            webhook_id = %delivery.webhook_id,
+            attempt = delivery.attempts + 1,
+            request_id = delivery.request_id,
+        );
+
+        let request_id = delivery.request_id.clone();
+        let result = correlation::resume(request_id, send(&client, &config, &delivery))
+            .instrument(span.clone())
+            .await;
+
+        match result {
+            Ok(()) => {
+                database
+                    .mark_delivered(delivery.event_id, delivery.webhook_id)
+                    .await?;
+                delivered += 1;
+            }
+            Err(err) => {
+                let retry_in = retry_delay(delivery.attempts);
+                span.in_scope(|| warn!(?retry_in, "Cannot deliver webhook: {err:#}"));
+
+                database
+                    .mark_failed(
+                        delivery.event_id,
+                        delivery.webhook_id,
+                        &format!("{err:#}"),
+                        retry_in,
+                    )
+                    .await?;
+                failed += 1;
+            }
+        }
+    }
+
+    Ok(format!("{delivered} delivered, {failed} failed"))
+}
+
+async fn send(
+    client: &reqwest::Client,
+    config: &WebhooksConfig,
+    delivery: &PendingDelivery,
+) -> Result<()> {
+    let body = WebhookBody {
+        content: summary(delivery),
+        event_id: delivery.event_id,
+        event: &delivery.kind,
+        occurred_at: delivery.occurred_at,
+        mare: &delivery.payload,
+    };
+    let body = serde_json::to_vec(&body)?;
+
+    let request = client
+        .post(&delivery.url)
+        .timeout(config.timeout)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.kind)
+        .header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", sign(&delivery.secret, &body)),
+        )
+        .body(body);
+
+    correlation::outbound(request)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+fn summary(delivery: &PendingDelivery) -> String {
+    let name = delivery.payload["name"].as_str().unwrap_or("unknown");
+    let breed = delivery.payload["breed"].as_str().unwrap_or("unknown");
+
+    format!("Mare \"{name}\" ({breed}) was {}", delivery.kind)
+}
+
+/// Hex encoded HMAC-SHA256 of `body`, so receivers can verify the sender.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(body);
+
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.clamp(0, 16) as u32;
+
+    FIRST_RETRY_DELAY
+        .saturating_mul(2_u32.pow(exponent))
+        .min(MAX_RETRY_DELAY)
+}
@@ -1,13 +1,22 @@
 mod app;
+mod archive;
+mod budget;
+mod cli;
+mod config;
+mod correlation;
 mod database;
+mod jobs;
 pub mod logging;
+mod storage;
 mod utils;
 
-pub async fn run() -> anyhow::Result<()> {
+pub use cli::Cli;
+
+pub async fn run(cli: Cli) -> anyhow::Result<()> {
     // TODO .env file?
     // if let Err(err) = dotenvy::dotenv() {
     //     println!("Failed to load .env file: {}", err);
     // }
 
-    app::run().await
+    cli.run().await
 }
@@ -1,9 +1,12 @@
 use tracing::info;
+use tracing_subscriber::filter::{LevelFilter, Targets};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{EnvFilter, Layer};
 use url::Url;
 
+use crate::budget::BudgetLayer;
+
 pub struct LogControl {
     task: tokio::task::JoinHandle<()>,
     controller: tracing_loki::BackgroundTaskController,
@@ -11,13 +14,15 @@ pub struct LogControl {
 
 impl LogControl {
     pub fn init_logging() -> Self {
-        let sqlx_filter = EnvFilter::new(
-            "\
-            info,\
-            sqlx::query=warn,\
-            hyper=warn\
-        ",
-        );
+        let sqlx_filter = || {
+            EnvFilter::new(
+                "\
+                info,\
+                sqlx::query=warn,\
+                hyper=warn\
+            ",
+            )
+        };
 
         // let sqlx_layer = tracing_subscriber::fmt::layer().with_filter(sqlx_filter);
 
@@ -34,11 +39,18 @@ impl LogControl {
 
         // register our layer with `tracing`.
         tracing_subscriber::registry()
-            .with(layer)
+            .with(layer.with_filter(sqlx_filter()))
             // .with(sqlx_layer)
-            // One could add more layers here, for example logging to stdout:
-            .with(tracing_subscriber::fmt::Layer::new())
-            .with(sqlx_filter)
+            // stdout is reserved for the output of CLI commands, e.g. `export`
+            .with(
+                tracing_subscriber::fmt::Layer::new()
+                    .with_writer(std::io::stderr)
+                    .with_filter(sqlx_filter()),
+            )
+            // budget reports list the steps of a request regardless of the log level
+            .with(BudgetLayer.with_filter(
+                Targets::new().with_target(env!("CARGO_CRATE_NAME"), LevelFilter::TRACE),
+            ))
             .init();
 
         // The background task needs to be spawned so the logs actually get
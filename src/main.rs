@@ -1,13 +1,16 @@
+use clap::Parser;
 use futures::{FutureExt, TryFutureExt};
 use std::{panic::AssertUnwindSafe, process::ExitCode};
 use tracing::error;
 
 #[tokio::main]
 async fn main() -> ExitCode {
+    let cli = mare_website::Cli::parse();
+
     let log_control = mare_website::logging::LogControl::init_logging();
 
     let website = AssertUnwindSafe(async {
-        let result = mare_website::run().await;
+        let result = mare_website::run(cli).await;
 
         match result {
             Ok(()) => ExitCode::SUCCESS,
@@ -0,0 +1,137 @@
+//! Files of the images kept in mare galleries.
+//!
+//! Images are downloaded once when they are kept and served from here, so
+//! galleries don't depend on derpibooru being up. Files are named after the
+//! derpibooru id of the image, which stays the same when record ids are
+//! migrated, and are shared by all galleries that keep the same image.
+
+use std::{
+    io::{self, ErrorKind},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use tracing::{info, instrument, Level};
+
+#[derive(Debug, Clone)]
+pub(crate) struct ImageStorage {
+    root: PathBuf,
+}
+
+/// Metadata of a stored image file.
+#[derive(Debug, Clone)]
+pub(crate) struct StoredFile {
+    pub(crate) size: u64,
+    /// Unknown if the filesystem doesn't track access times.
+    pub(crate) accessed_at: Option<DateTime<Utc>>,
+    /// When the file was downloaded.
+    pub(crate) modified_at: Option<DateTime<Utc>>,
+}
+
+impl ImageStorage {
+    pub(crate) fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, image_id: i64) -> PathBuf {
+        self.root.join(image_id.to_string())
+    }
+
+    /// Writes the file through a temporary one, so readers never see it half-written.
+    #[instrument(level = Level::DEBUG, skip(self, bytes), fields(size = bytes.len()))]
+    pub(crate) async fn save(&self, image_id: i64, bytes: &[u8]) -> io::Result<()> {
+        tokio::fs::create_dir_all(&self.root).await?;
+
+        let path = self.path(image_id);
+        let partial = path.with_extension("partial");
+
+        tokio::fs::write(&partial, bytes).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        info!(image_id, "Stored image");
+
+        Ok(())
+    }
+
+    /// Contents of the file, `None` if it is not stored.
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn read(&self, image_id: i64) -> io::Result<Option<Vec<u8>>> {
+        match tokio::fs::read(self.path(image_id)).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Metadata of the file, `None` if it is not stored.
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn metadata(&self, image_id: i64) -> io::Result<Option<StoredFile>> {
+        let metadata = match tokio::fs::metadata(self.path(image_id)).await {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        Ok(Some(StoredFile {
+            size: metadata.len(),
+            accessed_at: metadata.accessed().ok().map(DateTime::from),
+            modified_at: metadata.modified().ok().map(DateTime::from),
+        }))
+    }
+
+    /// Ids of all stored images.
+    #[instrument(level = Level::DEBUG, skip(self))]
+    pub(crate) async fn list(&self) -> io::Result<Vec<i64>> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut image_ids = Vec::new();
+
+        // Skips partial files and anything else that isn't named after an image
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(image_id) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+            {
+                image_ids.push(image_id);
+            }
+        }
+
+        Ok(image_ids)
+    }
+
+    /// Removes the file. Files that are not stored are skipped.
+    #[instrument(level = Level::INFO, skip(self))]
+    pub(crate) async fn remove(&self, image_id: i64) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(image_id)).await {
+            Ok(()) => {
+                info!(image_id, "Removed stored image");
+                Ok(())
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Content type of an image by the extension of its derpibooru `url`.
+pub(crate) fn content_type(url: &str) -> &'static str {
+    let extension = url
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("webm") => "video/webm",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
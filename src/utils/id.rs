@@ -0,0 +1,281 @@
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, TryLockError,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgHasArrayType, PgTypeInfo, PgValueRef},
+    Decode, Encode, Postgres, Type,
+};
+use ulid::{MonotonicError, Ulid};
+use uuid::Uuid;
+
+/// Alphabet of nanoids, safe to use in URLs.
+const NANOID_ALPHABET: &[u8; 64] =
+    b"_-0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+const NANOID_LENGTH: usize = 21;
+
+/// Record id stored as text: a ULID, a UUID or a nanoid, depending on the
+/// [`IdFormat`] that was used when the record was created. Parsing fails on
+/// malformed ids instead of panicking, so ids from URLs and from the database
+/// can be trusted once they are `DbId`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum DbId {
+    Ulid(Ulid),
+    Uuid(Uuid),
+    NanoId([u8; NANOID_LENGTH]),
+}
+
+impl DbId {
+    /// Whether `id` is a valid id written the way [`DbId`] writes it, e.g. not a
+    /// lowercase ULID. Only canonical ids can be compared as strings.
+    pub(crate) fn is_canonical(id: &str) -> bool {
+        id.parse::<DbId>()
+            .is_ok_and(|parsed| parsed.to_string() == id)
+    }
+}
+
+impl Display for DbId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbId::Ulid(ulid) => f.write_str(&ulid.to_string()),
+            DbId::Uuid(uuid) => Display::fmt(&uuid.hyphenated(), f),
+            // Only built from `NANOID_ALPHABET`, which is ASCII
+            DbId::NanoId(bytes) => f.write_str(std::str::from_utf8(bytes).unwrap_or_default()),
+        }
+    }
+}
+
+impl From<Ulid> for DbId {
+    fn from(value: Ulid) -> Self {
+        Self::Ulid(value)
+    }
+}
+
+impl From<Uuid> for DbId {
+    fn from(value: Uuid) -> Self {
+        Self::Uuid(value)
+    }
+}
+
+impl FromStr for DbId {
+    type Err = anyhow::Error;
+
+    /// Formats are told apart by their length.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.len() {
+            ulid::ULID_LEN => Ulid::from_string(s)
+                .map(DbId::Ulid)
+                .map_err(|err| anyhow!("\"{s}\" is not a valid ULID: {err}")),
+            36 => Uuid::try_parse(s)
+                .map(DbId::Uuid)
+                .map_err(|err| anyhow!("\"{s}\" is not a valid UUID: {err}")),
+            NANOID_LENGTH => {
+                if !s.bytes().all(|byte| NANOID_ALPHABET.contains(&byte)) {
+                    return Err(anyhow!("\"{s}\" is not a valid nanoid: invalid character"));
+                }
+
+                let mut bytes = [0; NANOID_LENGTH];
+                bytes.copy_from_slice(s.as_bytes());
+
+                Ok(DbId::NanoId(bytes))
+            }
+            _ => Err(anyhow!(
+                "\"{s}\" is not a valid id: invalid length, expected a ULID, a UUID or a nanoid"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for DbId {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Serialize for DbId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DbId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl Type<Postgres> for DbId {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl PgHasArrayType for DbId {
+    fn array_type_info() -> PgTypeInfo {
+        <String as PgHasArrayType>::array_type_info()
+    }
+
+    fn array_compatible(ty: &PgTypeInfo) -> bool {
+        <String as PgHasArrayType>::array_compatible(ty)
+    }
+}
+
+/// Written in canonical form, so ids bound to queries compare equal to the stored ones.
+impl Encode<'_, Postgres> for DbId {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        <&str as Encode<Postgres>>::encode(self.to_string().as_str(), buf)
+    }
+}
+
+impl Decode<'_, Postgres> for DbId {
+    fn decode(value: PgValueRef<'_>) -> Result<Self, BoxDynError> {
+        let value = <&str as Decode<Postgres>>::decode(value)?;
+
+        Ok(value.parse::<DbId>()?)
+    }
+}
+
+/// Source of ids for new rows.
+pub(crate) trait IdGenerator: Send + Sync {
+    fn generate(&self) -> DbId;
+}
+
+/// Contention of [`UlidGenerator`]s, exported by `/admin/metrics`.
+#[derive(Debug)]
+pub(crate) struct UlidContention {
+    /// Times the random part ran out within a millisecond.
+    pub(crate) overflows: AtomicU64,
+    /// Times a caller had to wait for another one to finish generating.
+    pub(crate) lock_waits: AtomicU64,
+}
+
+pub(crate) static ULID_CONTENTION: UlidContention = UlidContention {
+    overflows: AtomicU64::new(0),
+    lock_waits: AtomicU64::new(0),
+};
+
+#[derive(Default)]
+struct UlidState {
+    generator: ulid::Generator,
+    last: Ulid,
+}
+
+#[derive(Default, Clone)]
+pub(crate) struct UlidGenerator(Arc<Mutex<UlidState>>);
+
+impl IdGenerator for UlidGenerator {
+    /// Never waits for the clock: when a millisecond runs out of ids,
+    /// the next one is taken in advance, keeping the ids monotonic.
+    fn generate(&self) -> DbId {
+        let mut state = match self.0.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::WouldBlock) => {
+                ULID_CONTENTION.lock_waits.fetch_add(1, Ordering::Relaxed);
+                self.0.lock().unwrap()
+            }
+            Err(TryLockError::Poisoned(err)) => panic!("{err}"),
+        };
+
+        let ulid = match state.generator.generate() {
+            Ok(ulid) => ulid,
+            Err(MonotonicError::Overflow) => {
+                ULID_CONTENTION.overflows.fetch_add(1, Ordering::Relaxed);
+
+                let next_millisecond = state.last.datetime() + Duration::from_millis(1);
+
+                // Can't overflow, as the new millisecond is later than the last id
+                state
+                    .generator
+                    .generate_from_datetime(next_millisecond)
+                    .expect("ULID of a later millisecond")
+            }
+        };
+
+        state.last = ulid;
+
+        DbId::Ulid(ulid)
+    }
+}
+
+/// Version 7 UUIDs start with the creation time, like ULIDs,
+/// and can be cast to the native `uuid` type of Postgres.
+#[derive(Default, Clone)]
+pub(crate) struct UuidV7Generator;
+
+impl IdGenerator for UuidV7Generator {
+    fn generate(&self) -> DbId {
+        DbId::Uuid(Uuid::now_v7())
+    }
+}
+
+/// Short random ids. They aren't ordered by creation time, which records
+/// are listed by through their `created_at`.
+#[derive(Default, Clone)]
+pub(crate) struct NanoIdGenerator;
+
+impl IdGenerator for NanoIdGenerator {
+    fn generate(&self) -> DbId {
+        let mut rng = rand::thread_rng();
+
+        DbId::NanoId(std::array::from_fn(|_| {
+            NANOID_ALPHABET[rng.gen_range(0..NANOID_ALPHABET.len())]
+        }))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IdFormat {
+    #[default]
+    Ulid,
+    UuidV7,
+    NanoId,
+}
+
+impl IdFormat {
+    /// Values that can be parsed into an `IdFormat`.
+    pub(crate) const VALUES: [&'static str; 3] = ["ulid", "uuidv7", "nanoid"];
+
+    pub(crate) fn generator(self) -> Arc<dyn IdGenerator> {
+        match self {
+            IdFormat::Ulid => Arc::new(UlidGenerator::default()),
+            IdFormat::UuidV7 => Arc::new(UuidV7Generator),
+            IdFormat::NanoId => Arc::new(NanoIdGenerator),
+        }
+    }
+}
+
+impl FromStr for IdFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ulid" => Ok(IdFormat::Ulid),
+            "uuidv7" => Ok(IdFormat::UuidV7),
+            "nanoid" => Ok(IdFormat::NanoId),
+            _ => Err(anyhow!(
+                "Unknown id format \"{s}\". Valid values: {}.",
+                Self::VALUES.join(", ")
+            )),
+        }
+    }
+}
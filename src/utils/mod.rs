@@ -1 +1,2 @@
-pub(crate) mod ulid;
+pub(crate) mod id;
+pub(crate) mod token;
@@ -0,0 +1,19 @@
+use rand::RngCore;
+
+/// Random hex string of `bytes` bytes, for secrets and identifiers that must not be guessed.
+pub(crate) fn random_hex(bytes: usize) -> String {
+    let mut token = vec![0; bytes];
+    rand::thread_rng().fill_bytes(&mut token);
+
+    hex::encode(token)
+}
+
+/// Compares secrets without leaking the length of their common prefix through timing.
+pub(crate) fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    left.len() == right.len()
+        && left
+            .iter()
+            .zip(right)
+            .fold(0, |acc, (left, right)| acc | (left ^ right))
+            == 0
+}